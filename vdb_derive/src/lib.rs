@@ -1,6 +1,9 @@
 use proc_macro::{self, TokenStream};
 use quote::quote;
-use syn::{parse_macro_input, Attribute, Data, DataEnum, DataStruct, DeriveInput, Ident};
+use syn::{
+    parse_macro_input, Attribute, Data, DataEnum, DataStruct, DeriveInput, Fields, Ident, Index,
+    Member,
+};
 
 #[proc_macro_derive(Value, attributes(vdb_value))]
 pub fn derive(input: TokenStream) -> TokenStream {
@@ -38,10 +41,22 @@ fn process_struct(data_struct: DataStruct, ident: Ident, _attrs: &[Attribute]) -
 
     let mut fields_des_block = quote! {};
     let mut fields_ser_block = quote! {};
+    let mut fields_schema_block = quote! {};
+    let name = ident.to_string();
 
     for (field, opt) in field_and_opts.into_iter() {
         let ident = field.ident.unwrap();
         let index = opt.index;
+        let ty = field.ty;
+        let field_name = ident.to_string();
+
+        fields_schema_block.extend(quote! {
+            vdb_value::FieldSchema {
+                name: #field_name.to_string(),
+                index: #index,
+                schema: <#ty as vdb_value::Schema>::schema(),
+            },
+        });
 
         fields_des_block.extend(quote! {
             #index => {
@@ -50,9 +65,10 @@ fn process_struct(data_struct: DataStruct, ident: Ident, _attrs: &[Attribute]) -
         });
 
         fields_ser_block.extend(quote! {
-            output.write_field_header(self.#ident.ty(), #index);
-            self.#ident.to_output(output);
-
+            if !self.#ident.is_absent() {
+                output.write_field_header(self.#ident.ty(), #index);
+                self.#ident.to_output(output);
+            }
         });
     }
 
@@ -81,13 +97,283 @@ fn process_struct(data_struct: DataStruct, ident: Ident, _attrs: &[Attribute]) -
                 output.write_stop();
             }
         }
+
+        impl vdb_value::Schema for #ident {
+            fn schema() -> vdb_value::TypeSchema {
+                vdb_value::TypeSchema::Struct(vdb_value::StructSchema {
+                    name: #name.to_string(),
+                    fields: vec![#fields_schema_block],
+                })
+            }
+        }
+    };
+    output.into()
+}
+
+/// Key of struct fields as components in declaration order,
+/// `#[vdb_key(desc)]` field is a descending component
+#[proc_macro_derive(Key, attributes(vdb_key))]
+pub fn derive_key(input: TokenStream) -> TokenStream {
+    let DeriveInput { ident, data, .. } = parse_macro_input!(input);
+
+    match data {
+        Data::Struct(data_struct) => process_key_struct(data_struct, ident),
+        Data::Enum(_) | Data::Union(_) => {
+            syn::Error::new_spanned(ident, "Key can only be derived for structs")
+                .to_compile_error()
+                .into()
+        }
+    }
+}
+
+fn process_key_struct(data_struct: DataStruct, ident: Ident) -> TokenStream {
+    let count = data_struct.fields.len();
+
+    let mut append_block = quote! {};
+    let mut vars = vec![];
+    let mut fields_block = quote! {};
+
+    for (i, field) in data_struct.fields.into_iter().enumerate() {
+        let member = match field.ident {
+            Some(ident) => Member::Named(ident),
+            None => Member::Unnamed(Index::from(i)),
+        };
+        let var = quote::format_ident!("c{}", i);
+        let desc = attrs::has_attrs_flag(&field.attrs, "vdb_key", "desc");
+
+        if desc {
+            append_block.extend(quote! {
+                key.append_desc(&vdb_key::Component::from(value.#member));
+            });
+            fields_block.extend(quote! {
+                #member: {
+                    let vdb_key::Desc(v) = #var.try_into_at(#i)?;
+                    v
+                },
+            });
+        } else {
+            append_block.extend(quote! {
+                key.append_component(&vdb_key::Component::from(value.#member));
+            });
+            fields_block.extend(quote! {
+                #member: #var.try_into_at(#i)?,
+            });
+        }
+        vars.push(var);
+    }
+
+    let output = quote! {
+        impl From<#ident> for vdb_key::Key {
+            fn from(value: #ident) -> Self {
+                let mut key = vdb_key::Key::new();
+                #append_block
+                key
+            }
+        }
+
+        impl TryFrom<vdb_key::Key> for #ident {
+            type Error = vdb_key::Error;
+
+            fn try_from(value: vdb_key::Key) -> Result<Self, Self::Error> {
+                let [#(#vars),*] = value.try_components_exact::<#count>()?;
+                Ok(Self {
+                    #fields_block
+                })
+            }
+        }
     };
     output.into()
 }
 
-fn process_enum(data_enum: DataEnum, ident: Ident, attrs: &[Attribute]) -> TokenStream {
-    let _ = (data_enum, ident, attrs);
-    unimplemented!()
+/// Enum is encoded as a struct with a single field, field index is the
+/// variant index and field value is a struct of variant fields. Tuple
+/// variant fields are indexed by position from 1. Unknown variant decodes
+/// to the `#[vdb_value(other)]` unit variant if any, otherwise fails
+fn process_enum(data_enum: DataEnum, ident: Ident, _attrs: &[Attribute]) -> TokenStream {
+    let mut variants_des_block = quote! {};
+    let mut variants_ser_block = quote! {};
+    let mut variants_schema_block = quote! {};
+    let mut other = None;
+    let mut other_without_index = false;
+    let name = ident.to_string();
+
+    for variant in data_enum.variants {
+        let variant_ident = variant.ident;
+        let variant_name = variant_ident.to_string();
+        let index = attrs::get_attrs_value(&variant.attrs, "vdb_value", "index")
+            .map(|index| index.parse::<u8>().expect("failed to parse index"));
+
+        if attrs::has_attrs_flag(&variant.attrs, "vdb_value", "other") {
+            assert!(
+                matches!(variant.fields, Fields::Unit),
+                "other variant must be a unit variant"
+            );
+            other = Some(variant_ident.clone());
+        }
+
+        let mut vars = vec![];
+        let mut members = vec![];
+        let mut fields_des_block = quote! {};
+        let mut fields_ser_block = quote! {};
+        let mut fields_schema_block = quote! {};
+        let mut field_decls = quote! {};
+
+        for (i, field) in variant.fields.iter().enumerate() {
+            let field_index = match field.ident {
+                Some(_) => attrs::get_attrs_value(&field.attrs, "vdb_value", "index")
+                    .expect("index must specified")
+                    .parse::<u8>()
+                    .expect("failed to parse index"),
+                None => i as u8 + 1,
+            };
+            let field_name = match field.ident {
+                Some(ref ident) => ident.to_string(),
+                None => i.to_string(),
+            };
+            let var = quote::format_ident!("f_{}", i);
+            let ty = &field.ty;
+
+            field_decls.extend(quote! {
+                let mut #var: #ty = Default::default();
+            });
+            fields_des_block.extend(quote! {
+                #field_index => {
+                    #var.from_input(input)?;
+                }
+            });
+            fields_ser_block.extend(quote! {
+                if !#var.is_absent() {
+                    output.write_field_header(#var.ty(), #field_index);
+                    #var.to_output(output);
+                }
+            });
+            fields_schema_block.extend(quote! {
+                vdb_value::FieldSchema {
+                    name: #field_name.to_string(),
+                    index: #field_index,
+                    schema: <#ty as vdb_value::Schema>::schema(),
+                },
+            });
+            vars.push(var);
+            members.push(field.ident.clone());
+        }
+
+        let pattern = match variant.fields {
+            Fields::Named(_) => {
+                let members = members.into_iter().flatten();
+                quote! { Self::#variant_ident { #(#members: #vars),* } }
+            }
+            Fields::Unnamed(_) => quote! { Self::#variant_ident ( #(#vars),* ) },
+            Fields::Unit => quote! { Self::#variant_ident },
+        };
+
+        let index = match index {
+            Some(index) => index,
+            None => {
+                assert!(
+                    other.as_ref() == Some(&variant_ident),
+                    "index must specified"
+                );
+                // other variant without index is written as empty struct
+                variants_ser_block.extend(quote! {
+                    #pattern => {}
+                });
+                other_without_index = true;
+                continue;
+            }
+        };
+
+        variants_des_block.extend(quote! {
+            #index => {
+                #field_decls
+                while let Some((ty, index)) = input.read_non_stop_field()? {
+                    match index {
+                        #fields_des_block
+                        _ => {
+                            input.skip_field(ty)?;
+                        }
+                    }
+                }
+                *self = #pattern;
+            }
+        });
+
+        variants_ser_block.extend(quote! {
+            #pattern => {
+                output.write_field_header(vdb_value::Ty::Struct, #index);
+                #fields_ser_block
+                output.write_stop();
+            }
+        });
+
+        variants_schema_block.extend(quote! {
+            vdb_value::FieldSchema {
+                name: #variant_name.to_string(),
+                index: #index,
+                schema: vdb_value::TypeSchema::Struct(vdb_value::StructSchema {
+                    name: #variant_name.to_string(),
+                    fields: vec![#fields_schema_block],
+                }),
+            },
+        });
+    }
+
+    // empty struct is the other variant, any variant read replaces it
+    let empty_block = match other {
+        Some(ref other) if other_without_index => quote! {
+            *self = Self::#other;
+        },
+        _ => quote! {},
+    };
+
+    let unknown_block = match other {
+        Some(other) => quote! {
+            input.skip_field(ty)?;
+            *self = Self::#other;
+        },
+        None => quote! {
+            return Err(vdb_value::Error::DecodeUnknownVariant(index));
+        },
+    };
+
+    let output = quote! {
+        impl vdb_value::Value for #ident {
+            fn ty(&self) -> vdb_value::Ty {
+                vdb_value::Ty::Struct
+            }
+
+            fn from_input(&mut self, input: &mut vdb_value::InputProtocol<'_>) -> Result<(), vdb_value::Error> {
+                #empty_block
+                while let Some((ty, index)) = input.read_non_stop_field()? {
+                    match index {
+                        #variants_des_block
+                        _ => {
+                            #unknown_block
+                        }
+                    }
+                }
+
+                Ok(())
+            }
+
+            fn to_output(&self, output: &mut vdb_value::OutProtocol<'_>) {
+                match self {
+                    #variants_ser_block
+                }
+                output.write_stop();
+            }
+        }
+
+        impl vdb_value::Schema for #ident {
+            fn schema() -> vdb_value::TypeSchema {
+                vdb_value::TypeSchema::Struct(vdb_value::StructSchema {
+                    name: #name.to_string(),
+                    fields: vec![#variants_schema_block],
+                })
+            }
+        }
+    };
+    output.into()
 }
 
 mod attrs {
@@ -118,4 +404,18 @@ mod attrs {
 
         None
     }
+
+    /// whether `#[group(name)]` is present
+    pub fn has_attrs_flag(attrs: &[Attribute], group: &str, name: &str) -> bool {
+        attrs.iter().any(|attr| {
+            attr.path.is_ident(group)
+                && attr.tokens.clone().into_iter().any(|token| match token {
+                    TokenTree::Group(group) => group
+                        .stream()
+                        .into_iter()
+                        .any(|token| token.to_string().eq(name)),
+                    _ => false,
+                })
+        })
+    }
 }
@@ -0,0 +1,154 @@
+//! Collated string components, a string is stored as its folded sort key,
+//! optionally followed by the original string, so lookups ignore case or
+//! accents while the original spelling is kept and breaks ties.
+use crate::{Component, Key};
+use unicode_normalization::char::is_combining_mark;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Collation {
+    /// case folded after NFKC normalization, "Café" and "CAFE\u{301}" are
+    /// equal, so are "straße" and "STRASSE"
+    CaseInsensitive,
+    /// like `CaseInsensitive`, also strips accents, "Café" and "cafe" are equal
+    AccentInsensitive,
+}
+
+impl Collation {
+    /// folded sort key of s
+    pub fn fold(&self, s: &str) -> String {
+        match self {
+            Collation::CaseInsensitive => case_fold(s).nfkc().collect(),
+            Collation::AccentInsensitive => case_fold(s)
+                .nfkd()
+                .filter(|c| !is_combining_mark(*c))
+                .nfc()
+                .collect(),
+        }
+    }
+}
+
+/// full case folding of NFKC normalized s, normalizing first so
+/// compatibility characters like "ℌ" are folded as the letter they map to.
+/// lowercasing the uppercase also folds "ß" and "SS" to "ss"
+fn case_fold(s: &str) -> String {
+    s.nfkc().collect::<String>().to_uppercase().to_lowercase()
+}
+
+impl Component {
+    /// folded string component
+    pub fn collated(s: &str, collation: Collation) -> Component {
+        Component::Str(collation.fold(s))
+    }
+}
+
+impl Key {
+    /// append folded string
+    pub fn append_collated(&mut self, s: &str, collation: Collation) {
+        self.append_str(&collation.fold(s));
+    }
+
+    /// append folded string followed by original string
+    pub fn append_collated_with_original(&mut self, s: &str, collation: Collation) {
+        self.append_collated(s, collation);
+        self.append_str(s);
+    }
+
+    /// encoded bytes range `[lower, higher)` of keys starting with self and
+    /// then collated s, with or without the original suffix
+    pub fn collated_range(&self, s: &str, collation: Collation) -> (Vec<u8>, Vec<u8>) {
+        let mut key = self.clone();
+        key.append_collated(s, collation);
+        key.prefix_range()
+    }
+
+    /// encoded bytes range `[lower, higher)` of keys starting with self and
+    /// then a collated string starting with collated prefix
+    pub fn collated_prefix_range(&self, prefix: &str, collation: Collation) -> (Vec<u8>, Vec<u8>) {
+        let mut key = self.clone();
+        key.append_collated(prefix, collation);
+
+        // drop the terminator, so longer strings are in range, utf8 never
+        // contains 0xff and an escaped zero byte is `0x00 0xff`, so
+        // strings continuing prefix stay below higher
+        let mut lower = key.into_bytes();
+        lower.pop();
+        let mut higher = lower.clone();
+        higher.push(0xff);
+        (lower, higher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn in_range(range: &(Vec<u8>, Vec<u8>), key: &Key) -> bool {
+        range.0.as_slice() <= key.as_bytes() && key.as_bytes() < range.1.as_slice()
+    }
+
+    #[test]
+    fn test_collation_fold() {
+        let ci = Collation::CaseInsensitive;
+        let ai = Collation::AccentInsensitive;
+
+        assert_eq!(ci.fold("Café"), ci.fold("CAFE\u{301}"));
+        assert_ne!(ci.fold("Café"), ci.fold("cafe"));
+        assert_eq!(ai.fold("Café"), "cafe");
+        assert_eq!(ai.fold("ÅNGSTRÖM"), "angstrom");
+        assert_eq!(ci.fold("ﬁle"), "file");
+        assert_eq!(ci.fold("ℌ"), "h");
+        assert_eq!(ci.fold("straße"), ci.fold("STRASSE"));
+        assert_eq!(ai.fold("Straße"), "strasse");
+    }
+
+    #[test]
+    fn test_collated_order_and_ranges() {
+        let collation = Collation::AccentInsensitive;
+        let names = ["bob", "Émile", "alice", "ALICE", "emily", "Alicé", "Bob"];
+
+        let mut keys = names
+            .iter()
+            .map(|name| {
+                let mut key = Key::new();
+                key.append_collated_with_original(name, collation);
+                key
+            })
+            .collect::<Vec<_>>();
+        keys.sort();
+        let sorted = keys
+            .iter()
+            .map(|key| String::try_from(key.as_components()[1].clone()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            sorted,
+            vec!["ALICE", "Alicé", "alice", "Bob", "bob", "Émile", "emily"]
+        );
+
+        let exact = Key::new().collated_range("Alice", collation);
+        let matched = keys.iter().filter(|key| in_range(&exact, key)).count();
+        assert_eq!(matched, 3);
+
+        let prefix = Key::new().collated_prefix_range("EM", collation);
+        let matched = keys.iter().filter(|key| in_range(&prefix, key)).count();
+        assert_eq!(matched, 2);
+
+        // case only collation keeps accented names apart
+        let exact = Key::new().collated_range("alice", Collation::CaseInsensitive);
+        let mut key = Key::new();
+        key.append_collated_with_original("Alicé", Collation::CaseInsensitive);
+        assert!(!in_range(&exact, &key));
+
+        // preceding components
+        let mut key = Key::from(1);
+        key.append_collated("Émile", collation);
+        assert!(in_range(
+            &Key::from(1).collated_prefix_range("e", collation),
+            &key
+        ));
+        assert!(!in_range(
+            &Key::from(2).collated_prefix_range("e", collation),
+            &key
+        ));
+    }
+}
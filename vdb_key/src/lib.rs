@@ -1,26 +1,171 @@
+use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::f64;
 use std::fmt::{Debug, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(thiserror::Error, Debug)]
+mod collation;
+mod text;
+
+pub use collation::Collation;
+
+pub use vdb_derive::Key;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
 pub enum Error {
     #[error("[vdb_key] type mismatch")]
     TypeMismatch,
+
+    #[error("[vdb_key] type mismatch of component {index}, found {found:?}")]
+    ComponentMismatch { index: usize, found: Ty },
+
+    #[error("[vdb_key] expect {expected} components, found {found}")]
+    ComponentCount { expected: usize, found: usize },
+
+    #[error("[vdb_key] unknown type tag {tag} at {offset}")]
+    UnknownTag { tag: u8, offset: usize },
+
+    #[error("[vdb_key] truncated component at {offset}")]
+    Truncated { offset: usize },
+
+    #[error("[vdb_key] unterminated bytes at {offset}")]
+    UnterminatedBytes { offset: usize },
+
+    #[error("[vdb_key] invalid escape in bytes at {offset}")]
+    InvalidEscape { offset: usize },
+
+    #[error("[vdb_key] invalid utf8 string at {offset}")]
+    InvalidUtf8 { offset: usize },
+
+    #[error("[vdb_key] invalid bool byte at {offset}")]
+    InvalidBool { offset: usize },
+
+    #[error("[vdb_key] invalid key text at {offset}: {reason}")]
+    InvalidText { offset: usize, reason: &'static str },
 }
 
-#[derive(Ord, PartialOrd, Eq, PartialEq)]
+/// Version of key bytes layout, stored by tables to detect keys written
+/// by older versions. Version 0 wrote i64 as two's complement and f64 as
+/// raw IEEE bytes, so negative numbers did not sort by bytes. Version 1
+/// escaped zero byte in bytes as two zero bytes, which left no room for
+/// the zero `Null` type tag.
+pub const KEY_FORMAT_VERSION: i64 = 2;
+
+/// Type tag of component, components of different types are ordered by tag
+#[derive(Debug, Clone, Copy, Ord, PartialOrd, Eq, PartialEq)]
 #[repr(u8)]
 pub enum Ty {
+    Null = 0,
     I64 = 1,
     F64 = 2,
     Bytes = 3,
+    Bool = 4,
+    U64 = 5,
+    Str = 6,
+    Timestamp = 7,
+    Desc = 8,
 }
 
 #[derive(Debug, Clone)]
 pub enum Component {
+    /// absent value, sorts before all other components
+    Null,
     I64(i64),
     F64(f64),
     Bytes(Vec<u8>),
+    Bool(bool),
+    U64(u64),
+    /// utf8 checked string
+    Str(String),
+    /// microseconds since unix epoch
+    Timestamp(i64),
+    /// inner component in descending order, encoded as bitwise inverted
+    /// bytes of inner component
+    Desc(Box<Component>),
+}
+
+/// Wraps value to be a descending component, e.g: `(tenant, Desc(created_at))`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Desc<T>(pub T);
+
+impl<T: Into<Component>> From<Desc<T>> for Component {
+    fn from(v: Desc<T>) -> Self {
+        Self::Desc(Box::new(v.0.into()))
+    }
+}
+
+impl<T: TryFrom<Component, Error = Error>> TryFrom<Component> for Desc<T> {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::Desc(inner) => Ok(Desc(T::try_from(*inner)?)),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+/// Component borrowed from key bytes, bytes and strings are borrowed
+/// unless they have to be unescaped
+#[derive(Debug, Clone, PartialEq)]
+pub enum ComponentRef<'a> {
+    Null,
+    I64(i64),
+    F64(f64),
+    Bytes(Cow<'a, [u8]>),
+    Bool(bool),
+    U64(u64),
+    Str(Cow<'a, str>),
+    Timestamp(i64),
+    Desc(Box<ComponentRef<'a>>),
+}
+
+impl ComponentRef<'_> {
+    pub fn into_component(self) -> Component {
+        match self {
+            ComponentRef::Null => Component::Null,
+            ComponentRef::I64(v) => Component::I64(v),
+            ComponentRef::F64(v) => Component::F64(v),
+            ComponentRef::Bytes(v) => Component::Bytes(v.into_owned()),
+            ComponentRef::Bool(v) => Component::Bool(v),
+            ComponentRef::U64(v) => Component::U64(v),
+            ComponentRef::Str(v) => Component::Str(v.into_owned()),
+            ComponentRef::Timestamp(v) => Component::Timestamp(v),
+            ComponentRef::Desc(inner) => Component::Desc(Box::new(inner.into_component())),
+        }
+    }
+}
+
+impl From<ComponentRef<'_>> for Component {
+    fn from(v: ComponentRef<'_>) -> Self {
+        v.into_component()
+    }
+}
+
+/// Borrowing iterator over components of a key, see `Key::components`
+pub struct Components<'a> {
+    reader: Reader<'a>,
+    failed: bool,
+}
+
+impl<'a> Components<'a> {
+    /// byte offset of the next component
+    pub fn offset(&self) -> usize {
+        self.reader.pos
+    }
+}
+
+impl<'a> Iterator for Components<'a> {
+    type Item = Result<ComponentRef<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.reader.is_empty() {
+            return None;
+        }
+        let component = self.reader.component(KEY_FORMAT_VERSION, false);
+        self.failed = component.is_err();
+        Some(component)
+    }
 }
 
 impl From<i8> for Component {
@@ -65,17 +210,163 @@ impl From<Vec<u8>> for Component {
     }
 }
 
+impl From<bool> for Component {
+    fn from(v: bool) -> Self {
+        Self::Bool(v)
+    }
+}
+
+impl From<u64> for Component {
+    fn from(v: u64) -> Self {
+        Self::U64(v)
+    }
+}
+
+impl From<String> for Component {
+    fn from(v: String) -> Self {
+        Self::Str(v)
+    }
+}
+
+impl From<&str> for Component {
+    fn from(v: &str) -> Self {
+        Self::Str(v.to_string())
+    }
+}
+
+impl From<SystemTime> for Component {
+    fn from(v: SystemTime) -> Self {
+        let micros = match v.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_micros() as i64,
+            Err(e) => -(e.duration().as_micros() as i64),
+        };
+        Self::Timestamp(micros)
+    }
+}
+
+/// None becomes `Null`
+impl<T: Into<Component>> From<Option<T>> for Component {
+    fn from(v: Option<T>) -> Self {
+        match v {
+            None => Self::Null,
+            Some(v) => v.into(),
+        }
+    }
+}
+
+impl TryFrom<Component> for i64 {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::I64(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+impl TryFrom<Component> for f64 {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::F64(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+impl TryFrom<Component> for Vec<u8> {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::Bytes(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+impl TryFrom<Component> for bool {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::Bool(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+impl TryFrom<Component> for u64 {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::U64(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+impl TryFrom<Component> for String {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::Str(v) => Ok(v),
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
+impl TryFrom<Component> for SystemTime {
+    type Error = Error;
+
+    fn try_from(value: Component) -> Result<Self, Self::Error> {
+        match value {
+            Component::Timestamp(micros) if micros >= 0 => {
+                Ok(UNIX_EPOCH + Duration::from_micros(micros as u64))
+            }
+            Component::Timestamp(micros) => {
+                Ok(UNIX_EPOCH - Duration::from_micros(micros.unsigned_abs()))
+            }
+            _ => Err(Error::TypeMismatch),
+        }
+    }
+}
+
 impl Component {
+    /// convert component at `index` of a key, type mismatch is reported
+    /// with the index
+    pub fn try_into_at<T: TryFrom<Component, Error = Error>>(
+        self,
+        index: usize,
+    ) -> Result<T, Error> {
+        let found = self.ty();
+        T::try_from(self).map_err(|e| match e {
+            Error::TypeMismatch => Error::ComponentMismatch { index, found },
+            e => e,
+        })
+    }
+
     fn ty(&self) -> Ty {
         match self {
+            Component::Null => Ty::Null,
             Component::I64(_) => Ty::I64,
             Component::F64(_) => Ty::F64,
             Component::Bytes(_) => Ty::Bytes,
+            Component::Bool(_) => Ty::Bool,
+            Component::U64(_) => Ty::U64,
+            Component::Str(_) => Ty::Str,
+            Component::Timestamp(_) => Ty::Timestamp,
+            Component::Desc(_) => Ty::Desc,
         }
     }
 
     fn byte_len_hint(&self) -> usize {
         match self {
+            Component::Null => 1,
             Component::I64(_) => 9,
             Component::F64(_) => 9,
             Component::Bytes(ref bytes) => {
@@ -83,18 +374,18 @@ impl Component {
                 // with extra space, so just use naive number
                 1 + bytes.len() + 1
             }
+            Component::Bool(_) => 2,
+            Component::U64(_) => 9,
+            Component::Str(ref s) => 1 + s.len() + 1,
+            Component::Timestamp(_) => 9,
+            Component::Desc(ref inner) => 1 + inner.byte_len_hint() + 1,
         }
     }
 }
 
 impl PartialEq<Self> for Component {
     fn eq(&self, other: &Self) -> bool {
-        match (self, other) {
-            (Component::I64(l), Component::I64(r)) => l.eq(r),
-            (Component::F64(l), Component::F64(r)) => l.eq(r),
-            (Component::Bytes(ref l), Component::Bytes(ref r)) => l.eq(r),
-            _ => false,
-        }
+        self.cmp(other).is_eq()
     }
 }
 
@@ -110,11 +401,15 @@ impl Ord for Component {
     fn cmp(&self, other: &Self) -> Ordering {
         match (self, other) {
             (Component::I64(l), Component::I64(r)) => l.cmp(r),
-            (Component::F64(l), Component::F64(r)) => {
-                // we treat Nan same here
-                l.partial_cmp(r).unwrap_or(Ordering::Equal)
-            }
+            // total order, same as encoded bytes: -NaN < -inf < -0 < 0 < inf < NaN
+            (Component::F64(l), Component::F64(r)) => l.total_cmp(r),
             (Component::Bytes(ref l), Component::Bytes(ref r)) => l.cmp(r),
+            (Component::Null, Component::Null) => Ordering::Equal,
+            (Component::Bool(l), Component::Bool(r)) => l.cmp(r),
+            (Component::U64(l), Component::U64(r)) => l.cmp(r),
+            (Component::Str(ref l), Component::Str(ref r)) => l.cmp(r),
+            (Component::Timestamp(l), Component::Timestamp(r)) => l.cmp(r),
+            (Component::Desc(ref l), Component::Desc(ref r)) => r.cmp(l),
             (l, r) => l.ty().cmp(&r.ty()),
         }
     }
@@ -123,14 +418,17 @@ impl Ord for Component {
 /// A maximum three parts component key, each part is a byte array.
 /// the serialized bytes should hold same compare order as the struct
 /// itself.
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
+#[derive(Clone, Default, Ord, PartialOrd, Eq, PartialEq)]
 pub struct Key {
     storage: Vec<u8>,
 }
 
 impl Debug for Key {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{:?}", self.as_components())
+        match self.try_components() {
+            Ok(components) => write!(f, "{:?}", components),
+            Err(_) => write!(f, "Invalid({:?})", self.storage),
+        }
     }
 }
 
@@ -140,24 +438,67 @@ impl From<Component> for Key {
     }
 }
 
-impl From<i64> for Key {
-    fn from(val: i64) -> Self {
+macro_rules! impl_key_conversion {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Key {
+                fn from(val: $ty) -> Self {
+                    Self::from(Component::from(val))
+                }
+            }
+
+            impl TryFrom<Key> for $ty {
+                type Error = Error;
+
+                fn try_from(value: Key) -> Result<Self, Self::Error> {
+                    let [c] = value.try_components_exact::<1>()?;
+                    c.try_into_at(0)
+                }
+            }
+        )*
+    };
+}
+
+impl_key_conversion!(i64, f64, u64, bool, String, Vec<u8>, SystemTime);
+
+// integer literals default to i32, so `Key::from(1)` keeps working
+impl From<i32> for Key {
+    fn from(val: i32) -> Self {
         Self::from(Component::from(val))
     }
 }
 
-impl TryFrom<Key> for i64 {
-    type Error = Error;
+impl From<&str> for Key {
+    fn from(val: &str) -> Self {
+        Self::from(Component::from(val))
+    }
+}
 
-    fn try_from(value: Key) -> Result<Self, Self::Error> {
-        let mut it = value.as_components().into_iter();
-        match (it.next(), it.next()) {
-            (Some(Component::I64(val)), None) => Ok(val),
-            _ => Err(Error::TypeMismatch),
+macro_rules! impl_key_tuple_conversion {
+    ($n:literal; $($t:ident $v:ident $i:tt),*) => {
+        impl<$($t: Into<Component>),*> From<($($t,)*)> for Key {
+            fn from(val: ($($t,)*)) -> Self {
+                let mut key = Key::new();
+                $(key.append_component(&val.$i.into());)*
+                key
+            }
         }
-    }
+
+        impl<$($t: TryFrom<Component, Error = Error>),*> TryFrom<Key> for ($($t,)*) {
+            type Error = Error;
+
+            fn try_from(value: Key) -> Result<Self, Self::Error> {
+                let [$($v),*] = value.try_components_exact::<$n>()?;
+                Ok(($($v.try_into_at($i)?,)*))
+            }
+        }
+    };
 }
 
+impl_key_tuple_conversion!(2; A a 0, B b 1);
+impl_key_tuple_conversion!(3; A a 0, B b 1, C c 2);
+impl_key_tuple_conversion!(4; A a 0, B b 1, C c 2, D d 3);
+
 impl From<&[Component]> for Key {
     fn from(components: &[Component]) -> Self {
         let cap = components
@@ -192,14 +533,33 @@ impl Key {
         Self { storage: bytes }
     }
 
+    /// Validate bytes is a key of current format
+    pub fn try_from_bytes(bytes: Vec<u8>) -> Result<Self, Error> {
+        parse_components(bytes.as_slice(), KEY_FORMAT_VERSION)?;
+        Ok(Self { storage: bytes })
+    }
+
+    /// Re-encode key bytes written in older key format `format_version`
+    pub fn try_from_legacy_bytes(bytes: &[u8], format_version: i64) -> Result<Self, Error> {
+        Ok(Key::from(
+            parse_components(bytes, format_version)?.as_slice(),
+        ))
+    }
+
+    /// Re-encode key bytes written in older key format `format_version`,
+    /// panics on invalid bytes, see `try_from_legacy_bytes`
+    pub fn from_legacy_bytes(bytes: &[u8], format_version: i64) -> Self {
+        Self::try_from_legacy_bytes(bytes, format_version).expect("invalid key bytes")
+    }
+
     pub fn append_i64(&mut self, val: i64) {
         self.storage.push(Ty::I64 as u8);
-        self.storage.extend_from_slice(&val.to_be_bytes());
+        self.storage.extend_from_slice(&encode_i64(val));
     }
 
     pub fn append_f64(&mut self, val: f64) {
         self.storage.push(Ty::F64 as u8);
-        self.storage.extend_from_slice(&val.to_be_bytes());
+        self.storage.extend_from_slice(&encode_f64(val));
     }
 
     pub fn append_bytes(&mut self, val: &[u8]) {
@@ -208,7 +568,47 @@ impl Key {
         self.storage.extend_from_slice(escaped.as_slice());
     }
 
+    pub fn append_null(&mut self) {
+        self.storage.push(Ty::Null as u8);
+    }
+
+    pub fn append_bool(&mut self, val: bool) {
+        self.storage.push(Ty::Bool as u8);
+        self.storage.push(val as u8);
+    }
+
+    pub fn append_u64(&mut self, val: u64) {
+        self.storage.push(Ty::U64 as u8);
+        self.storage.extend_from_slice(&val.to_be_bytes());
+    }
+
+    pub fn append_str(&mut self, val: &str) {
+        self.storage.push(Ty::Str as u8);
+        let escaped = escape_bytes(val.as_bytes());
+        self.storage.extend_from_slice(escaped.as_slice());
+    }
+
+    /// append timestamp in microseconds since unix epoch
+    pub fn append_timestamp(&mut self, micros: i64) {
+        self.storage.push(Ty::Timestamp as u8);
+        self.storage.extend_from_slice(&encode_i64(micros));
+    }
+
+    /// append component in descending order
+    pub fn append_desc(&mut self, component: &Component) {
+        self.storage.push(Ty::Desc as u8);
+        self.append_component_inverted(component, true);
+    }
+
     pub fn append_component(&mut self, component: &Component) {
+        self.append_component_inverted(component, false);
+    }
+
+    /// append component, bitwise inverted if `invert`. Inverted bytes end
+    /// with two separators, so a shorter value sorts after longer ones
+    /// whatever follows it
+    fn append_component_inverted(&mut self, component: &Component, invert: bool) {
+        let start = self.storage.len();
         match component {
             Component::I64(v) => {
                 self.append_i64(*v);
@@ -219,63 +619,194 @@ impl Key {
             Component::Bytes(v) => {
                 self.append_bytes(v);
             }
+            Component::Null => {
+                self.append_null();
+            }
+            Component::Bool(v) => {
+                self.append_bool(*v);
+            }
+            Component::U64(v) => {
+                self.append_u64(*v);
+            }
+            Component::Str(v) => {
+                self.append_str(v);
+            }
+            Component::Timestamp(v) => {
+                self.append_timestamp(*v);
+            }
+            Component::Desc(inner) => {
+                self.storage.push(Ty::Desc as u8);
+                self.append_component_inverted(inner, !invert);
+                if invert {
+                    // inner is encoded already, only invert the tag
+                    self.storage[start] = !self.storage[start];
+                }
+                return;
+            }
+        }
+
+        if invert {
+            if matches!(component, Component::Bytes(_) | Component::Str(_)) {
+                self.storage.push(BYTE_SEPARATOR);
+            }
+            for b in self.storage[start..].iter_mut() {
+                *b = !*b;
+            }
         }
     }
 
     /// parse to components, if bytes exists, then it will be
-    /// cloned.
+    /// cloned. panics on invalid bytes, see `try_components`
     pub fn as_components(&self) -> Vec<Component> {
-        let mut current = self.storage.as_slice();
-        let mut components: Vec<Component> = Vec::new();
-
-        // each component is at least 8 bytes, plus one type byte
-        while !current.is_empty() {
-            let ty = match current[0] {
-                1 => Ty::I64,
-                2 => Ty::F64,
-                3 => Ty::Bytes,
-                _ => panic!("invalid"),
-            };
+        self.try_components().expect("invalid key bytes")
+    }
 
-            current = &current[1..];
+    /// parse to components, fails if bytes are not a valid key
+    pub fn try_components(&self) -> Result<Vec<Component>, Error> {
+        parse_components(self.storage.as_slice(), KEY_FORMAT_VERSION)
+    }
 
-            match ty {
-                Ty::I64 => {
-                    let mut buf = [0u8; 8];
-                    buf.copy_from_slice(&current[..8]);
-                    let v = i64::from_be_bytes(buf);
-                    components.push(Component::I64(v));
-                    current = &current[8..];
-                }
-                Ty::F64 => {
-                    let mut buf = [0u8; 8];
-                    buf.copy_from_slice(&current[..8]);
-                    let v = f64::from_be_bytes(buf);
-                    components.push(Component::F64(v));
-                    current = &current[8..];
-                }
-                Ty::Bytes => {
-                    let (new_current, bytes) = parse_bytes(current);
-                    components.push(Component::Bytes(bytes));
-                    current = new_current;
+    /// parse to exactly `N` components
+    pub fn try_components_exact<const N: usize>(&self) -> Result<[Component; N], Error> {
+        let components = self.try_components()?;
+        let found = components.len();
+        components
+            .try_into()
+            .map_err(|_| Error::ComponentCount { expected: N, found })
+    }
+
+    /// iterate components without copying bytes, iteration stops after
+    /// the first error
+    pub fn components(&self) -> Components<'_> {
+        Components {
+            reader: Reader {
+                bytes: self.storage.as_slice(),
+                pos: 0,
+            },
+            failed: false,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.storage.as_slice()
+    }
+
+    pub fn component_count(&self) -> Result<usize, Error> {
+        self.components()
+            .try_fold(0, |count, component| component.map(|_| count + 1))
+    }
+
+    /// whether components of prefix are the leading components of self
+    pub fn starts_with(&self, prefix: &Key) -> bool {
+        if !self.storage.starts_with(prefix.as_bytes()) {
+            return false;
+        }
+
+        // byte prefix may end inside a component, e.g: bytes "a" is a byte
+        // prefix of bytes "a\0", so also check it ends at a component boundary
+        let mut components = self.components();
+        while components.offset() < prefix.storage.len() {
+            if !matches!(components.next(), Some(Ok(_))) {
+                return false;
+            }
+        }
+        components.offset() == prefix.storage.len()
+    }
+
+    /// keep first n components
+    pub fn truncate_components(&mut self, n: usize) -> Result<(), Error> {
+        let len = self.components_len(n)?;
+        self.storage.truncate(len);
+        Ok(())
+    }
+
+    /// compare first n components, by encoded bytes
+    pub fn cmp_components(&self, other: &Key, n: usize) -> Result<Ordering, Error> {
+        let l = &self.storage[..self.components_len(n)?];
+        let r = &other.storage[..other.components_len(n)?];
+        Ok(l.cmp(r))
+    }
+
+    /// encoded bytes range `[lower, higher)` of all keys starting with self,
+    /// including self
+    pub fn prefix_range(&self) -> (Vec<u8>, Vec<u8>) {
+        // tags are below 0xff, while an escaped separator is followed by 0xff,
+        // so only keys continuing self with more components are in range
+        let mut higher = self.storage.clone();
+        higher.push(0xff);
+        (self.storage.clone(), higher)
+    }
+
+    /// byte length of first n components, or all if less than n
+    fn components_len(&self, n: usize) -> Result<usize, Error> {
+        let mut components = self.components();
+        for _ in 0..n {
+            match components.next() {
+                None => break,
+                Some(component) => {
+                    component?;
                 }
             }
         }
+        Ok(components.offset())
+    }
+}
 
-        components
+/// flip sign bit, so negative numbers sort before positive ones
+fn encode_i64(val: i64) -> [u8; 8] {
+    ((val as u64) ^ (1 << 63)).to_be_bytes()
+}
+
+fn decode_i64(buf: [u8; 8]) -> i64 {
+    (u64::from_be_bytes(buf) ^ (1 << 63)) as i64
+}
+
+/// flip all bits of negative numbers and only the sign bit of positive
+/// ones, bytes then follow `f64::total_cmp` order
+fn encode_f64(val: f64) -> [u8; 8] {
+    let bits = val.to_bits();
+    let bits = if bits >> 63 == 1 {
+        !bits
+    } else {
+        bits | (1 << 63)
+    };
+    bits.to_be_bytes()
+}
+
+fn decode_f64(buf: [u8; 8]) -> f64 {
+    let bits = u64::from_be_bytes(buf);
+    let bits = if bits >> 63 == 1 {
+        bits & !(1 << 63)
+    } else {
+        !bits
+    };
+    f64::from_bits(bits)
+}
+
+/// parse bytes written in key format `format_version` to components
+fn parse_components(bytes: &[u8], format_version: i64) -> Result<Vec<Component>, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    let mut components: Vec<Component> = Vec::new();
+    while !reader.is_empty() {
+        components.push(reader.component(format_version, false)?.into_component());
     }
+    Ok(components)
 }
 
 const BYTE_SEPARATOR: u8 = 0u8;
 
+/// follows escaped separator, never a type tag, so a separator
+/// followed by anything else terminates bytes
+const BYTE_ESCAPE: u8 = 0xffu8;
+
 fn escape_bytes(val: &[u8]) -> Vec<u8> {
     let mut result = Vec::with_capacity(val.len() + 1);
 
     for b in val {
+        result.push(*b);
         if *b == BYTE_SEPARATOR {
-            result.push(BYTE_SEPARATOR);
+            result.push(BYTE_ESCAPE);
         }
-        result.push(*b);
     }
 
     result.push(BYTE_SEPARATOR);
@@ -283,37 +814,187 @@ fn escape_bytes(val: &[u8]) -> Vec<u8> {
     result
 }
 
-fn parse_bytes(val: &[u8]) -> (&[u8], Vec<u8>) {
-    let mut result = Vec::with_capacity(val.len());
-    let mut escaping = false;
+/// Cursor over key bytes, errors carry offset of the failed component
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
 
-    for (idx, b) in val.iter().enumerate() {
-        if *b == BYTE_SEPARATOR {
-            if escaping {
-                result.push(BYTE_SEPARATOR);
-                escaping = false;
-            } else {
-                escaping = true;
-            }
-            continue;
+impl<'a> Reader<'a> {
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize, offset: usize) -> Result<&'a [u8], Error> {
+        let taken = self
+            .bytes
+            .get(self.pos..self.pos + len)
+            .ok_or(Error::Truncated { offset })?;
+        self.pos += len;
+        Ok(taken)
+    }
+
+    fn take_8(&mut self, mask: u8, offset: usize) -> Result<[u8; 8], Error> {
+        let mut buf = [0u8; 8];
+        for (b, v) in buf.iter_mut().zip(self.take(8, offset)?.iter()) {
+            *b = v ^ mask;
         }
+        Ok(buf)
+    }
 
-        if escaping {
-            // escaping and met non separator, should skip
-            // the filled zeros if len less than 8
-            return (&val[idx..], result);
+    /// parse one component, bytes are bitwise inverted if `invert`
+    fn component(&mut self, format_version: i64, invert: bool) -> Result<ComponentRef<'a>, Error> {
+        let mask = if invert { 0xff } else { 0 };
+        let offset = self.pos;
+        let tag = self.take(1, offset)?[0] ^ mask;
+        let ty = match tag {
+            0 => Ty::Null,
+            1 => Ty::I64,
+            2 => Ty::F64,
+            3 => Ty::Bytes,
+            4 => Ty::Bool,
+            5 => Ty::U64,
+            6 => Ty::Str,
+            7 => Ty::Timestamp,
+            8 => Ty::Desc,
+            _ => return Err(Error::UnknownTag { tag, offset }),
+        };
+
+        Ok(match ty {
+            Ty::Null => ComponentRef::Null,
+            Ty::I64 | Ty::Timestamp => {
+                let buf = self.take_8(mask, offset)?;
+                let v = if format_version == 0 {
+                    i64::from_be_bytes(buf)
+                } else {
+                    decode_i64(buf)
+                };
+                match ty {
+                    Ty::I64 => ComponentRef::I64(v),
+                    _ => ComponentRef::Timestamp(v),
+                }
+            }
+            Ty::F64 => {
+                let buf = self.take_8(mask, offset)?;
+                if format_version == 0 {
+                    ComponentRef::F64(f64::from_be_bytes(buf))
+                } else {
+                    ComponentRef::F64(decode_f64(buf))
+                }
+            }
+            Ty::U64 => ComponentRef::U64(u64::from_be_bytes(self.take_8(mask, offset)?)),
+            Ty::Bool => match self.take(1, offset)?[0] ^ mask {
+                0 => ComponentRef::Bool(false),
+                1 => ComponentRef::Bool(true),
+                _ => return Err(Error::InvalidBool { offset }),
+            },
+            Ty::Bytes | Ty::Str => {
+                let bytes = if invert {
+                    Cow::Owned(self.inverted_bytes(offset)?)
+                } else if format_version < 2 {
+                    Cow::Owned(self.legacy_bytes(offset)?)
+                } else {
+                    self.bytes(offset)?
+                };
+                match ty {
+                    Ty::Bytes => ComponentRef::Bytes(bytes),
+                    _ => ComponentRef::Str(match bytes {
+                        Cow::Borrowed(bytes) => Cow::Borrowed(
+                            std::str::from_utf8(bytes)
+                                .map_err(|_| Error::InvalidUtf8 { offset })?,
+                        ),
+                        Cow::Owned(bytes) => Cow::Owned(
+                            String::from_utf8(bytes).map_err(|_| Error::InvalidUtf8 { offset })?,
+                        ),
+                    }),
+                }
+            }
+            Ty::Desc => ComponentRef::Desc(Box::new(self.component(format_version, !invert)?)),
+        })
+    }
+
+    /// escaped bytes, escaped separator is `0x00 0xff`, terminator is `0x00`.
+    /// borrowed if nothing escaped
+    fn bytes(&mut self, offset: usize) -> Result<Cow<'a, [u8]>, Error> {
+        let bytes = self.bytes;
+        let start = self.pos;
+        let mut unescaped: Option<Vec<u8>> = None;
+        loop {
+            let b = *bytes
+                .get(self.pos)
+                .ok_or(Error::UnterminatedBytes { offset })?;
+            self.pos += 1;
+
+            if b != BYTE_SEPARATOR {
+                if let Some(unescaped) = unescaped.as_mut() {
+                    unescaped.push(b);
+                }
+                continue;
+            }
+            if bytes.get(self.pos) == Some(&BYTE_ESCAPE) {
+                unescaped
+                    .get_or_insert_with(|| bytes[start..self.pos - 1].to_vec())
+                    .push(BYTE_SEPARATOR);
+                self.pos += 1;
+                continue;
+            }
+            return Ok(match unescaped {
+                Some(unescaped) => Cow::Owned(unescaped),
+                None => Cow::Borrowed(&bytes[start..self.pos - 1]),
+            });
         }
+    }
 
-        // then just push current char
-        result.push(*b);
+    /// inverted bytes, inverted escaped separator is `0xff 0x00`,
+    /// inverted terminator is `0xff 0xff`
+    fn inverted_bytes(&mut self, offset: usize) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::new();
+        loop {
+            let b = !*self
+                .bytes
+                .get(self.pos)
+                .ok_or(Error::UnterminatedBytes { offset })?;
+            self.pos += 1;
+
+            if b != BYTE_SEPARATOR {
+                result.push(b);
+                continue;
+            }
+            let next = !*self
+                .bytes
+                .get(self.pos)
+                .ok_or(Error::UnterminatedBytes { offset })?;
+            self.pos += 1;
+            match next {
+                BYTE_ESCAPE => result.push(BYTE_SEPARATOR),
+                BYTE_SEPARATOR => return Ok(result),
+                _ => return Err(Error::InvalidEscape { offset }),
+            }
+        }
     }
 
-    if escaping {
-        return (&val[val.len()..], result);
-    } else {
-        // didn't finish, it means the bytes is a valid key bytes
-        // panic now
-        panic!("panic now");
+    /// bytes of key format version 1 or earlier, which escaped
+    /// separator by doubling it
+    fn legacy_bytes(&mut self, offset: usize) -> Result<Vec<u8>, Error> {
+        let mut result = Vec::new();
+        loop {
+            let b = *self
+                .bytes
+                .get(self.pos)
+                .ok_or(Error::UnterminatedBytes { offset })?;
+            self.pos += 1;
+
+            if b != BYTE_SEPARATOR {
+                result.push(b);
+                continue;
+            }
+            if self.bytes.get(self.pos) == Some(&BYTE_SEPARATOR) {
+                result.push(BYTE_SEPARATOR);
+                self.pos += 1;
+                continue;
+            }
+            return Ok(result);
+        }
     }
 }
 
@@ -333,8 +1014,12 @@ mod tests {
             &b"||||||".to_vec(),
         ] {
             let escaped = escape_bytes(v);
-            let (_x, y) = parse_bytes(escaped.as_slice());
-            assert_eq!(v, y.as_slice());
+            let mut reader = Reader {
+                bytes: escaped.as_slice(),
+                pos: 0,
+            };
+            assert_eq!(v, reader.bytes(0).unwrap().as_ref());
+            assert!(reader.is_empty());
         }
     }
 
@@ -360,6 +1045,24 @@ mod tests {
                 Component::Bytes(b"d".to_vec()),
                 Component::I64(12231232131231232),
             ],
+            vec![
+                Component::Bytes(b"a\0".to_vec()),
+                Component::Null,
+                Component::Bool(true),
+                Component::U64(u64::MAX),
+                Component::Str("héllo\0".to_string()),
+                Component::Timestamp(-1),
+                Component::Null,
+            ],
+            vec![
+                Component::from(Desc(b"a\0b".to_vec())),
+                Component::I64(1),
+                Component::from(Desc("héllo")),
+                Component::from(Desc(-1.5)),
+                Component::Desc(Box::new(Component::from(Desc(b"x".to_vec())))),
+                Component::from(Desc(None::<i64>)),
+                Component::from(Desc(true)),
+            ],
         ]
         .into_iter()
         {
@@ -424,6 +1127,61 @@ mod tests {
                 vec![Component::Bytes(b"a\0".to_vec())],
                 Ordering::Less,
             ),
+            (
+                vec![Component::I64(-1)],
+                vec![Component::I64(1)],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::F64(-0.5)],
+                vec![Component::F64(0.25)],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::Null],
+                vec![Component::I64(i64::MIN)],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::Bytes(b"a".to_vec()), Component::Null],
+                vec![Component::Bytes(b"a\0".to_vec())],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::Bytes(b"a".to_vec()), Component::I64(1)],
+                vec![Component::Bytes(b"a\0".to_vec())],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::Bool(false)],
+                vec![Component::Bool(true)],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::U64(255)],
+                vec![Component::U64(256)],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::U64(u64::MAX)],
+                vec![Component::Str("".to_string())],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::Str("ab".to_string())],
+                vec![Component::Str("b".to_string())],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::Str("a".to_string())],
+                vec![Component::Str("a\0".to_string())],
+                Ordering::Less,
+            ),
+            (
+                vec![Component::Timestamp(-1)],
+                vec![Component::Timestamp(1)],
+                Ordering::Less,
+            ),
         ]
         .into_iter()
         {
@@ -440,4 +1198,331 @@ mod tests {
             assert_eq!(order, l_bytes.cmp(&r_bytes), "{} {}", l_str, r_str);
         }
     }
+
+    #[test]
+    fn test_numeric_byte_order() {
+        let ints = [
+            i64::MIN,
+            i64::MIN + 1,
+            -256,
+            -1,
+            0,
+            1,
+            255,
+            256,
+            i64::MAX - 1,
+            i64::MAX,
+        ];
+        let floats = [
+            -f64::NAN,
+            f64::NEG_INFINITY,
+            f64::MIN,
+            -1.5,
+            -f64::MIN_POSITIVE,
+            -0.,
+            0.,
+            f64::MIN_POSITIVE,
+            1.5,
+            f64::MAX,
+            f64::INFINITY,
+            f64::NAN,
+        ];
+        let components = ints
+            .into_iter()
+            .map(Component::from)
+            .chain(floats.into_iter().map(Component::from))
+            .collect::<Vec<_>>();
+
+        for (i, l) in components.iter().enumerate() {
+            let l_bytes = Key::from(l.clone()).into_bytes();
+            assert_eq!(
+                Key::load_from_bytes_unchecked(l_bytes.clone()).as_components(),
+                vec![l.clone()]
+            );
+
+            for (j, r) in components.iter().enumerate() {
+                let r_bytes = Key::from(r.clone()).into_bytes();
+                assert_eq!(i.cmp(&j), l.cmp(r), "{:?} {:?}", l, r);
+                assert_eq!(i.cmp(&j), l_bytes.cmp(&r_bytes), "{:?} {:?}", l, r);
+            }
+        }
+    }
+
+    #[test]
+    fn test_legacy_bytes() {
+        let mut legacy = vec![Ty::I64 as u8];
+        legacy.extend_from_slice(&(-2i64).to_be_bytes());
+        legacy.push(Ty::F64 as u8);
+        legacy.extend_from_slice(&(-0.5f64).to_be_bytes());
+        legacy.extend(Key::from(Component::from(b"a".to_vec())).into_bytes());
+
+        assert_eq!(
+            Key::from_legacy_bytes(&legacy, 0).as_components(),
+            vec![
+                Component::I64(-2),
+                Component::F64(-0.5),
+                Component::Bytes(b"a".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_component_conversions() {
+        assert_eq!(Component::from(true), Component::Bool(true));
+        assert_eq!(Component::from(7u64), Component::U64(7));
+        assert_eq!(Component::from("a"), Component::Str("a".to_string()));
+        assert_eq!(Component::from(None::<i64>), Component::Null);
+        assert_eq!(Component::from(Some(1i64)), Component::I64(1));
+
+        let before_epoch = UNIX_EPOCH - Duration::from_micros(1500);
+        assert_eq!(Component::from(before_epoch), Component::Timestamp(-1500));
+        assert_eq!(
+            SystemTime::try_from(Component::Timestamp(-1500)).unwrap(),
+            before_epoch
+        );
+
+        assert!(bool::try_from(Component::Bool(false)).is_ok());
+        assert_eq!(u64::try_from(Component::U64(7)).unwrap(), 7);
+        assert_eq!(String::try_from(Component::from("a")).unwrap(), "a");
+        assert!(matches!(
+            String::try_from(Component::Bytes(b"a".to_vec())),
+            Err(Error::TypeMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_desc_order() {
+        let values = [
+            Component::Null,
+            Component::I64(-1),
+            Component::I64(2),
+            Component::F64(-0.5),
+            Component::Bytes(b"".to_vec()),
+            Component::Bytes(b"a".to_vec()),
+            Component::Bytes(b"a\0".to_vec()),
+            Component::Bytes(b"ab".to_vec()),
+            Component::Str("a".to_string()),
+            Component::Str("b".to_string()),
+            Component::Bool(true),
+            Component::U64(3),
+        ];
+
+        // tenant ASC, value DESC, followed by an ASC tie breaker
+        let mut keys = vec![];
+        for tenant in [1i64, 2] {
+            for value in values.iter() {
+                for id in [Component::Null, Component::I64(1), Component::from("a")] {
+                    keys.push(vec![
+                        Component::I64(tenant),
+                        Component::Desc(Box::new(value.clone())),
+                        id,
+                    ]);
+                }
+            }
+        }
+
+        for l in keys.iter() {
+            let l_bytes = Key::from(l.as_slice()).into_bytes();
+            assert_eq!(
+                &Key::load_from_bytes_unchecked(l_bytes.clone()).as_components(),
+                l
+            );
+            for r in keys.iter() {
+                let r_bytes = Key::from(r.as_slice()).into_bytes();
+                assert_eq!(l.cmp(r), l_bytes.cmp(&r_bytes), "{:?} {:?}", l, r);
+            }
+        }
+
+        assert_eq!(
+            Component::from(Desc(1i64)).cmp(&Component::from(Desc(2i64))),
+            Ordering::Greater
+        );
+        assert_eq!(
+            Desc::<i64>::try_from(Component::from(Desc(1i64))).unwrap(),
+            Desc(1)
+        );
+    }
+
+    #[test]
+    fn test_try_components_errors() {
+        for (bytes, err) in [
+            (vec![9u8], Error::UnknownTag { tag: 9, offset: 0 }),
+            (vec![0, 1, 0x80], Error::Truncated { offset: 1 }),
+            (vec![3, b'a'], Error::UnterminatedBytes { offset: 0 }),
+            (vec![6, 0xff, 0], Error::InvalidUtf8 { offset: 0 }),
+            (vec![4, 2], Error::InvalidBool { offset: 0 }),
+            (
+                vec![8, !3, !b'a', 0xff, 0x12],
+                Error::InvalidEscape { offset: 1 },
+            ),
+        ] {
+            assert_eq!(Key::try_from_bytes(bytes.clone()).unwrap_err(), err);
+            assert_eq!(
+                Key::load_from_bytes_unchecked(bytes)
+                    .try_components()
+                    .unwrap_err(),
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_from_random_bytes() {
+        // xorshift, deterministic without extra dependency
+        let mut state = 0x2545f4914f6cdd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let valid = Key::from(
+            &[
+                Component::I64(-3),
+                Component::from("ab\0"),
+                Component::from(Desc(b"c\0".to_vec())),
+                Component::Bool(true),
+                Component::Null,
+            ][..],
+        )
+        .into_bytes();
+
+        for i in 0..20000 {
+            let bytes = if i % 2 == 0 {
+                // random bytes, biased to type tags and separators
+                (0..next() % 24)
+                    .map(|_| match next() % 4 {
+                        0 => (next() % 9) as u8,
+                        1 => 0,
+                        2 => 0xff,
+                        _ => next() as u8,
+                    })
+                    .collect::<Vec<_>>()
+            } else {
+                // truncated or mutated valid key
+                let mut bytes = valid.clone();
+                bytes.truncate((next() % (valid.len() as u64 + 1)) as usize);
+                if !bytes.is_empty() {
+                    let idx = (next() % bytes.len() as u64) as usize;
+                    bytes[idx] = next() as u8;
+                }
+                bytes
+            };
+
+            // never panics, and accepted bytes are canonical
+            if let Ok(key) = Key::try_from_bytes(bytes.clone()) {
+                let components = key.try_components().unwrap();
+                assert_eq!(Key::from(components.as_slice()).into_bytes(), bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_components_ref() {
+        let key = Key::from(
+            &[
+                Component::from("ab"),
+                Component::from(b"a\0b".to_vec()),
+                Component::from(Desc("c")),
+                Component::from(1i64),
+            ][..],
+        );
+
+        let components = key.components().collect::<Result<Vec<_>, _>>().unwrap();
+        assert!(matches!(
+            &components[0],
+            ComponentRef::Str(Cow::Borrowed("ab"))
+        ));
+        assert!(matches!(&components[1], ComponentRef::Bytes(Cow::Owned(v)) if v == b"a\0b"));
+        assert_eq!(
+            components
+                .into_iter()
+                .map(Component::from)
+                .collect::<Vec<_>>(),
+            key.as_components()
+        );
+        assert_eq!(key.component_count(), Ok(4));
+
+        let mut invalid = key.clone().into_bytes();
+        invalid.push(0xfe);
+        let invalid = Key::load_from_bytes_unchecked(invalid);
+        assert_eq!(invalid.components().filter(Result::is_err).count(), 1);
+        assert!(invalid.component_count().is_err());
+    }
+
+    #[test]
+    fn test_key_prefix_helpers() {
+        let key = Key::from(&[Component::from("a"), Component::from(1i64)][..]);
+        let prefix = Key::from(Component::from("a"));
+
+        assert!(key.starts_with(&prefix));
+        assert!(key.starts_with(&key));
+        assert!(key.starts_with(&Key::default()));
+        assert!(!prefix.starts_with(&key));
+        // byte prefix but not component prefix
+        assert!(!Key::from(Component::from("a\0")).starts_with(&prefix));
+
+        let (lower, higher) = prefix.prefix_range();
+        assert!(lower.as_slice() <= key.as_bytes() && key.as_bytes() < higher.as_slice());
+        let other = Key::from(Component::from("a\0"));
+        assert!(other.as_bytes() >= higher.as_slice());
+
+        let other = Key::from(&[Component::from("a"), Component::from(2i64)][..]);
+        assert_eq!(key.cmp_components(&other, 1), Ok(Ordering::Equal));
+        assert_eq!(key.cmp_components(&other, 2), Ok(Ordering::Less));
+        assert_eq!(key.cmp_components(&other, 3), Ok(Ordering::Less));
+
+        let mut truncated = key.clone();
+        truncated.truncate_components(5).unwrap();
+        assert_eq!(truncated, key);
+        truncated.truncate_components(1).unwrap();
+        assert_eq!(truncated, prefix);
+    }
+
+    #[test]
+    fn test_key_conversions() {
+        let key = Key::from(("tenant", 7u64, -1.5));
+        assert_eq!(
+            key.as_components(),
+            vec![
+                Component::from("tenant"),
+                Component::U64(7),
+                Component::F64(-1.5)
+            ]
+        );
+        assert_eq!(
+            <(String, u64, f64)>::try_from(key.clone()).unwrap(),
+            ("tenant".to_string(), 7, -1.5)
+        );
+        assert_eq!(
+            <(String, i64, f64)>::try_from(key.clone()),
+            Err(Error::ComponentMismatch {
+                index: 1,
+                found: Ty::U64
+            })
+        );
+        assert_eq!(
+            <(String, u64)>::try_from(key),
+            Err(Error::ComponentCount {
+                expected: 2,
+                found: 3
+            })
+        );
+
+        assert_eq!(Key::from(1), Key::from(1i64));
+        assert_eq!(String::try_from(Key::from("a")).unwrap(), "a");
+        assert_eq!(Vec::<u8>::try_from(Key::from(b"a".to_vec())).unwrap(), b"a");
+        assert_eq!(
+            i64::try_from(Key::from(1u64)),
+            Err(Error::ComponentMismatch {
+                index: 0,
+                found: Ty::U64
+            })
+        );
+        assert_eq!(
+            <(Desc<i64>, bool)>::try_from(Key::from((Desc(3), true))).unwrap(),
+            (Desc(3), true)
+        );
+    }
 }
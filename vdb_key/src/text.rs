@@ -0,0 +1,370 @@
+//! Text form of keys, e.g. `(123, 4.5, b"abc")`.
+//!
+//! - null: `null`
+//! - i64: `-12`
+//! - f64: `4.5`, `-0.0`, `1e100`, `inf`, `-inf`, nan as `nan(0x7ff8000000000000)`
+//! - bytes: `b"a\x00"`, printable ascii kept, other bytes as `\xNN`
+//! - bool: `true`, `false`
+//! - u64: `12u64`
+//! - str: `"a\"b"`, control chars as `\u{N}`
+//! - timestamp: `ts(-1500)`, microseconds since unix epoch
+//! - desc: `desc(<component>)`
+use crate::{Component, Error, Key};
+use std::fmt::{Display, Formatter, Write};
+use std::str::FromStr;
+
+impl Display for Component {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Component::Null => write!(f, "null"),
+            Component::I64(v) => write!(f, "{}", v),
+            Component::F64(v) if v.is_nan() => write!(f, "nan({:#018x})", v.to_bits()),
+            // debug format keeps `.0` to tell floats from integers
+            Component::F64(v) => write!(f, "{:?}", v),
+            Component::Bytes(v) => {
+                write!(f, "b\"")?;
+                for &b in v {
+                    match b {
+                        b'"' | b'\\' => write!(f, "\\{}", b as char)?,
+                        0x20..=0x7e => f.write_char(b as char)?,
+                        _ => write!(f, "\\x{:02x}", b)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Component::Bool(v) => write!(f, "{}", v),
+            Component::U64(v) => write!(f, "{}u64", v),
+            Component::Str(v) => {
+                write!(f, "\"")?;
+                for c in v.chars() {
+                    match c {
+                        '"' | '\\' => write!(f, "\\{}", c)?,
+                        c if c.is_control() => write!(f, "\\u{{{:x}}}", c as u32)?,
+                        c => f.write_char(c)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Component::Timestamp(v) => write!(f, "ts({})", v),
+            Component::Desc(inner) => write!(f, "desc({})", inner),
+        }
+    }
+}
+
+impl Display for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let components = match self.try_components() {
+            Ok(components) => components,
+            Err(_) => return write!(f, "Invalid({:?})", self.storage),
+        };
+
+        write!(f, "(")?;
+        for (i, component) in components.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", component)?;
+        }
+        write!(f, ")")
+    }
+}
+
+impl FromStr for Component {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { text: s, pos: 0 };
+        let component = parser.component()?;
+        parser.end()?;
+        Ok(component)
+    }
+}
+
+impl FromStr for Key {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser { text: s, pos: 0 };
+        let key = parser.key()?;
+        parser.end()?;
+        Ok(key)
+    }
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, offset: usize, reason: &'static str) -> Error {
+        Error::InvalidText { offset, reason }
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str, reason: &'static str) -> Result<(), Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(self.pos, reason))
+        }
+    }
+
+    fn end(&mut self) -> Result<(), Error> {
+        self.skip_whitespace();
+        if self.pos == self.text.len() {
+            Ok(())
+        } else {
+            Err(self.error(self.pos, "unexpected trailing text"))
+        }
+    }
+
+    fn next_char(&mut self, reason: &'static str) -> Result<char, Error> {
+        let c = self
+            .rest()
+            .chars()
+            .next()
+            .ok_or_else(|| self.error(self.pos, reason))?;
+        self.pos += c.len_utf8();
+        Ok(c)
+    }
+
+    /// `(c1, c2, ...)`, trailing comma allowed
+    fn key(&mut self) -> Result<Key, Error> {
+        self.expect("(", "expect `(`")?;
+        let mut key = Key::new();
+        loop {
+            if self.eat(")") {
+                return Ok(key);
+            }
+            key.append_component(&self.component()?);
+            if !self.eat(",") {
+                self.expect(")", "expect `,` or `)`")?;
+                return Ok(key);
+            }
+        }
+    }
+
+    fn component(&mut self) -> Result<Component, Error> {
+        self.skip_whitespace();
+        let start = self.pos;
+
+        if self.rest().starts_with("b\"") {
+            self.pos += 1;
+            return self.bytes().map(Component::Bytes);
+        }
+        if self.rest().starts_with('"') {
+            return self.str().map(Component::Str);
+        }
+        if self.eat("desc(") {
+            let inner = self.component()?;
+            self.expect(")", "expect `)`")?;
+            return Ok(Component::Desc(Box::new(inner)));
+        }
+        if self.eat("ts(") {
+            let v = self.number(start)?;
+            self.expect(")", "expect `)`")?;
+            return v
+                .parse()
+                .map(Component::Timestamp)
+                .map_err(|_| self.error(start, "invalid timestamp"));
+        }
+        if self.eat("nan(") {
+            let bits_start = self.pos;
+            let bits = self.number(start)?;
+            self.expect(")", "expect `)`")?;
+            let bits = bits
+                .strip_prefix("0x")
+                .and_then(|bits| u64::from_str_radix(bits, 16).ok())
+                .map(f64::from_bits)
+                .filter(|v| v.is_nan())
+                .ok_or_else(|| self.error(bits_start, "invalid nan bits"))?;
+            return Ok(Component::F64(bits));
+        }
+
+        let token = self.number(start)?;
+        match token {
+            "null" => Ok(Component::Null),
+            "true" => Ok(Component::Bool(true)),
+            "false" => Ok(Component::Bool(false)),
+            "inf" => Ok(Component::F64(f64::INFINITY)),
+            "-inf" => Ok(Component::F64(f64::NEG_INFINITY)),
+            _ => {
+                if let Some(v) = token.strip_suffix("u64") {
+                    return v
+                        .parse()
+                        .map(Component::U64)
+                        .map_err(|_| self.error(start, "invalid u64"));
+                }
+                if token.contains(['.', 'e', 'E']) {
+                    return token
+                        .parse()
+                        .map(Component::F64)
+                        .map_err(|_| self.error(start, "invalid f64"));
+                }
+                token
+                    .parse()
+                    .map(Component::I64)
+                    .map_err(|_| self.error(start, "invalid component"))
+            }
+        }
+    }
+
+    /// token of number or keyword characters, error at `start` if empty
+    fn number(&mut self, start: usize) -> Result<&'a str, Error> {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '.' | '_')))
+            .unwrap_or(rest.len());
+        if len == 0 {
+            return Err(self.error(start, "expect component"));
+        }
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, Error> {
+        self.pos += 1;
+        let mut bytes = vec![];
+        loop {
+            let escape_start = self.pos;
+            match self.next_char("unterminated bytes")? {
+                '"' => return Ok(bytes),
+                '\\' => match self.next_char("unterminated bytes")? {
+                    'x' => {
+                        let hex = self
+                            .rest()
+                            .get(..2)
+                            .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                            .ok_or_else(|| self.error(escape_start, "invalid escape"))?;
+                        self.pos += 2;
+                        bytes.push(hex);
+                    }
+                    c => bytes.push(self.simple_escape(c, escape_start)? as u8),
+                },
+                c if c.is_ascii() => bytes.push(c as u8),
+                _ => return Err(self.error(escape_start, "non ascii char in bytes")),
+            }
+        }
+    }
+
+    fn str(&mut self) -> Result<String, Error> {
+        self.pos += 1;
+        let mut s = String::new();
+        loop {
+            let escape_start = self.pos;
+            match self.next_char("unterminated string")? {
+                '"' => return Ok(s),
+                '\\' => match self.next_char("unterminated string")? {
+                    'u' => {
+                        let rest = self.rest();
+                        let c = rest
+                            .strip_prefix('{')
+                            .and_then(|rest| rest.split_once('}'))
+                            .and_then(|(hex, _)| {
+                                self.pos += hex.len() + 2;
+                                u32::from_str_radix(hex, 16).ok()
+                            })
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error(escape_start, "invalid escape"))?;
+                        s.push(c);
+                    }
+                    c => s.push(self.simple_escape(c, escape_start)?),
+                },
+                c => s.push(c),
+            }
+        }
+    }
+
+    fn simple_escape(&self, c: char, offset: usize) -> Result<char, Error> {
+        match c {
+            '"' | '\\' => Ok(c),
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            '0' => Ok('\0'),
+            _ => Err(self.error(offset, "invalid escape")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_round_trip() {
+        let components = vec![
+            Component::Null,
+            Component::I64(-12),
+            Component::F64(4.5),
+            Component::F64(-0.),
+            Component::F64(1e100),
+            Component::F64(f64::NEG_INFINITY),
+            Component::F64(-f64::NAN),
+            Component::Bytes(b"a\"\\\x00\xff".to_vec()),
+            Component::Bool(true),
+            Component::U64(u64::MAX),
+            Component::Str("a\"\\\n\u{7f}中".to_string()),
+            Component::Timestamp(-1500),
+            Component::Desc(Box::new(Component::from("z"))),
+        ];
+        let key = Key::from(components.as_slice());
+        let text = key.to_string();
+        assert_eq!(
+            text,
+            r#"(null, -12, 4.5, -0.0, 1e100, -inf, nan(0xfff8000000000000), b"a\"\\\x00\xff", true, 18446744073709551615u64, "a\"\\\u{a}\u{7f}中", ts(-1500), desc("z"))"#
+        );
+
+        let parsed = Key::from_str(&text).unwrap();
+        assert_eq!(parsed.as_bytes(), key.as_bytes());
+        assert_eq!(
+            Key::from_str(" ( 1 ,\"a\", ) ").unwrap(),
+            Key::from((1i64, "a"))
+        );
+        assert_eq!(Key::from_str("()").unwrap(), Key::new());
+        assert_eq!(
+            Component::from_str("b\"\\t\"").unwrap(),
+            Component::Bytes(b"\t".to_vec())
+        );
+    }
+
+    #[test]
+    fn test_text_errors() {
+        for (text, offset) in [
+            ("1", 0),
+            ("(1", 2),
+            ("(1) 2", 4),
+            ("(1, x)", 4),
+            ("(1.2.3)", 1),
+            ("(b\"\\q\")", 3),
+            ("(\"abc", 5),
+            ("(ts(1.5))", 1),
+            ("(nan(0x1))", 5),
+        ] {
+            match Key::from_str(text) {
+                Err(Error::InvalidText { offset: o, .. }) => assert_eq!(o, offset, "{}", text),
+                r => panic!("{} {:?}", text, r),
+            }
+        }
+    }
+}
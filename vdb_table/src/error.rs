@@ -11,4 +11,10 @@ pub enum Error {
 
     #[error("[vdb_table] Index missing {0}")]
     IndexMissing(String),
+
+    #[error("[vdb_table] Index {0} is building")]
+    IndexBuilding(String),
+
+    #[error("[vdb_table] Index {0} type mismatch")]
+    IndexTypeMismatch(String),
 }
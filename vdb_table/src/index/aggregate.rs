@@ -0,0 +1,286 @@
+use super::{expired_pk_condition, Extractor};
+use crate::{now_millis, Error};
+use std::collections::BTreeMap;
+use vdb_key::{Component, Key};
+
+/// Extract group key and numeric value to be aggregated from pk and value,
+/// None means the item is not aggregated
+pub type AggregateExtractor =
+    Box<dyn Fn(&[u8], &[u8]) -> Result<Option<(Key, f64)>, Error> + Send + Sync>;
+
+/// Aggregated values of one group. `sum` is maintained incrementally, so
+/// it may drift by floating point rounding after many updates, until the
+/// group's extreme is removed or `Table::repair_indexes` rebuilds it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+    pub count: i64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Aggregate {
+    fn empty() -> Self {
+        Self {
+            count: 0,
+            sum: 0.,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+}
+
+/// Build index extractor, entry ik is group key components followed by
+/// the value as F64 component
+pub fn aggregate_extractor(aggregate_extractor: AggregateExtractor) -> Extractor {
+    Box::new(move |pk, value| {
+        Ok(aggregate_extractor(pk, value)?
+            .into_iter()
+            .map(|(group, value)| {
+                let mut ik = group;
+                ik.append_f64(value);
+                ik.into_bytes()
+            })
+            .collect())
+    })
+}
+
+/// split entry ik into group key bytes and value
+pub(crate) fn parse_entry(ik: &[u8]) -> Result<(Vec<u8>, f64), Error> {
+    let mut components = Key::load_from_bytes_unchecked(ik.to_vec()).try_components()?;
+    match components.pop() {
+        Some(Component::F64(value)) => Ok((Key::from(components.as_slice()).into_bytes(), value)),
+        _ => Err(vdb_key::Error::TypeMismatch.into()),
+    }
+}
+
+/// Per group summary table of an aggregate index, maintained on each
+/// entry change of the index, so reading a group is a single row lookup
+pub(crate) struct AggregateSummary {
+    pub table_name: String,
+}
+
+impl AggregateSummary {
+    pub fn create_table(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(&format!(
+            r#"
+            create table if not exists {summary_table} (
+              grp blob primary key,
+              count integer,
+              sum real,
+              min real,
+              max real
+            )
+            "#,
+            summary_table = self.table_name,
+        ))?;
+        Ok(())
+    }
+
+    pub fn truncate(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(&format!(
+            r#"DELETE FROM {summary_table};"#,
+            summary_table = self.table_name,
+        ))?;
+        Ok(())
+    }
+
+    /// entry (ik, pk) added to index
+    pub fn add(&self, conn: &rusqlite::Connection, ik: &[u8]) -> Result<(), Error> {
+        let (group, value) = parse_entry(ik)?;
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT INTO {summary_table} (grp, count, sum, min, max) VALUES (:grp, 1, :value, :value, :value)
+               ON CONFLICT(grp) DO UPDATE SET count = count + 1, sum = sum + :value, min = MIN(min, :value), max = MAX(max, :value)"#,
+            summary_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":grp": group,
+            ":value": value,
+        })?;
+        Ok(())
+    }
+
+    /// entry (ik, pk) removed from index, `data_table` is the index data table,
+    /// used to recompute min and max when an extreme value is removed
+    pub fn remove(
+        &self,
+        conn: &rusqlite::Connection,
+        data_table: &str,
+        ik: &[u8],
+    ) -> Result<(), Error> {
+        let (group, value) = parse_entry(ik)?;
+
+        let current = match self.get(conn, &group)? {
+            None => return Ok(()),
+            Some(current) => current,
+        };
+
+        if current.count <= 1 {
+            return self.delete(conn, &group);
+        }
+
+        if value <= current.min || value >= current.max {
+            // removed an extreme, recompute from the rest entries of group,
+            // which also drops rounding error accumulated in sum
+            return match self.compute(conn, data_table, &group, None)? {
+                Some(aggregate) => self.put(conn, &group, &aggregate),
+                None => self.delete(conn, &group),
+            };
+        }
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"UPDATE {summary_table} SET count = count - 1, sum = sum - :value WHERE grp = :grp"#,
+            summary_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":grp": group,
+            ":value": value,
+        })?;
+        Ok(())
+    }
+
+    /// recompute all groups from entries of `data_table`, the index data table
+    pub fn rebuild(&self, conn: &rusqlite::Connection, data_table: &str) -> Result<(), Error> {
+        let mut groups = BTreeMap::<Vec<u8>, Aggregate>::new();
+        {
+            let mut stmt = conn.prepare(&format!(
+                r#"SELECT ik FROM {data_table}"#,
+                data_table = data_table,
+            ))?;
+            let mut rows = stmt.query([])?;
+            while let Some(row) = rows.next()? {
+                let entry_ik: Vec<u8> = row.get(0)?;
+                let (group, value) = parse_entry(&entry_ik)?;
+                groups
+                    .entry(group)
+                    .or_insert_with(Aggregate::empty)
+                    .add(value);
+            }
+        }
+
+        self.truncate(conn)?;
+        for (group, aggregate) in groups.iter() {
+            self.put(conn, group, aggregate)?;
+        }
+        Ok(())
+    }
+
+    fn put(
+        &self,
+        conn: &rusqlite::Connection,
+        group: &[u8],
+        aggregate: &Aggregate,
+    ) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {summary_table} (grp, count, sum, min, max) VALUES (:grp, :count, :sum, :min, :max)"#,
+            summary_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":grp": group,
+            ":count": aggregate.count,
+            ":sum": aggregate.sum,
+            ":min": aggregate.min,
+            ":max": aggregate.max,
+        })?;
+        Ok(())
+    }
+
+    fn delete(&self, conn: &rusqlite::Connection, group: &[u8]) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"DELETE FROM {summary_table} WHERE grp = :grp"#,
+            summary_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! { ":grp": group })?;
+        Ok(())
+    }
+
+    pub fn get(
+        &self,
+        conn: &rusqlite::Connection,
+        group: &[u8],
+    ) -> Result<Option<Aggregate>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT count, sum, min, max FROM {summary_table} WHERE grp = :grp"#,
+            summary_table = self.table_name,
+        ))?;
+        no_row_to_none!(
+            stmt.query_row(rusqlite::named_params! { ":grp": group }, |row| {
+                Ok(Aggregate {
+                    count: row.get(0)?,
+                    sum: row.get(1)?,
+                    min: row.get(2)?,
+                    max: row.get(3)?,
+                })
+            })
+        )
+        .map_err(Into::into)
+    }
+
+    /// aggregate group from entries of `data_table`, the index data table,
+    /// skipping entries of keys expired in `source_table` if given
+    pub fn compute(
+        &self,
+        conn: &rusqlite::Connection,
+        data_table: &str,
+        group: &[u8],
+        source_table: Option<&str>,
+    ) -> Result<Option<Aggregate>, Error> {
+        let (lower, higher) = Key::load_from_bytes_unchecked(group.to_vec()).prefix_range();
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT ik FROM {data_table} WHERE ik >= :lower AND ik < :higher {excluded}"#,
+            data_table = data_table,
+            excluded = source_table
+                .map(|source_table| format!("AND NOT {}", expired_pk_condition(source_table)))
+                .unwrap_or_default(),
+        ))?;
+        let now = now_millis();
+        let mut params: Vec<(&str, &dyn rusqlite::ToSql)> =
+            vec![(":lower", &lower), (":higher", &higher)];
+        if source_table.is_some() {
+            params.push((":now", &now));
+        }
+        let mut rows = stmt.query(params.as_slice())?;
+
+        let mut aggregate = Aggregate::empty();
+        while let Some(row) = rows.next()? {
+            let entry_ik: Vec<u8> = row.get(0)?;
+            let (entry_group, value) = parse_entry(&entry_ik)?;
+            // group may be a prefix of a longer group
+            if entry_group == group {
+                aggregate.add(value);
+            }
+        }
+        Ok((aggregate.count > 0).then_some(aggregate))
+    }
+
+    /// all groups ordered by group key
+    pub fn groups(&self, conn: &rusqlite::Connection) -> Result<Vec<(Vec<u8>, Aggregate)>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT grp, count, sum, min, max FROM {summary_table} ORDER BY grp"#,
+            summary_table = self.table_name,
+        ))?;
+        let mut rows = stmt.query([])?;
+
+        let mut groups = Vec::new();
+        while let Some(row) = rows.next()? {
+            groups.push((
+                row.get(0)?,
+                Aggregate {
+                    count: row.get(1)?,
+                    sum: row.get(2)?,
+                    min: row.get(3)?,
+                    max: row.get(4)?,
+                },
+            ));
+        }
+        Ok(groups)
+    }
+}
@@ -0,0 +1,152 @@
+use super::{Extractor, IndexOption};
+use crate::Error;
+use vdb_key::Key;
+use vdb_value::{DynamicStruct, DynamicValue, TypeSchema, Value};
+
+/// Declarative index definition, each path is a list of field indexes
+/// into the value's struct encoding, e.g: [3, 1] means field 1 of the
+/// struct at field 3. Values at paths form index key components in order.
+/// The definition is persisted in table's conf table, so the index can be
+/// maintained by any process opening the table.
+#[derive(Value, Default, Debug, Clone, PartialEq)]
+pub struct FieldPathIndexDef {
+    #[vdb_value(index = 1)]
+    pub name: String,
+
+    #[vdb_value(index = 2)]
+    pub version: i64,
+
+    #[vdb_value(index = 3)]
+    pub paths: Vec<Vec<u8>>,
+
+    /// key component kind of each path, paths without one take the kind of
+    /// the value read, so strings are indexed as bytes, see `new`
+    #[vdb_value(index = 4)]
+    pub kinds: Vec<FieldPathKind>,
+}
+
+/// Key component kind of a path value
+#[derive(Value, Default, Debug, Clone, Copy, PartialEq)]
+pub enum FieldPathKind {
+    #[default]
+    #[vdb_value(index = 1)]
+    I64,
+
+    #[vdb_value(index = 2)]
+    F64,
+
+    #[vdb_value(index = 3)]
+    Bytes,
+
+    /// utf8 checked bytes, indexed as `Component::Str`
+    #[vdb_value(index = 4)]
+    Str,
+}
+
+impl FieldPathKind {
+    /// kind of a scalar schema, `Option` of a scalar is the scalar,
+    /// None for other schemas. u64 is left out, its values above
+    /// `i64::MAX` would sort as negative
+    fn of_schema(schema: &TypeSchema) -> Option<Self> {
+        match schema {
+            TypeSchema::I64
+            | TypeSchema::Bool
+            | TypeSchema::I8
+            | TypeSchema::I16
+            | TypeSchema::I32
+            | TypeSchema::U8
+            | TypeSchema::U16
+            | TypeSchema::U32 => Some(Self::I64),
+            TypeSchema::F64 => Some(Self::F64),
+            TypeSchema::Bytes => Some(Self::Bytes),
+            TypeSchema::String => Some(Self::Str),
+            TypeSchema::Optional(inner) if !matches!(**inner, TypeSchema::Optional(_)) => {
+                Self::of_schema(inner)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// All declared indexes of a table, stored in conf table
+#[derive(Value, Default, Debug)]
+pub(crate) struct FieldPathIndexDefs {
+    #[vdb_value(index = 1)]
+    pub defs: Vec<FieldPathIndexDef>,
+}
+
+impl FieldPathIndexDef {
+    /// Definition with kinds resolved from `schema` of the value struct,
+    /// fails if a path doesn't lead to an I64, F64, bytes or string field
+    pub fn new(
+        name: &str,
+        version: i64,
+        schema: &TypeSchema,
+        paths: Vec<Vec<u8>>,
+    ) -> Result<Self, Error> {
+        let kinds = paths
+            .iter()
+            .map(|path| {
+                let mut current = schema;
+                for index in path.iter() {
+                    current = match current {
+                        TypeSchema::Optional(inner) => inner.as_ref(),
+                        schema => schema,
+                    };
+                    current = match current {
+                        TypeSchema::Struct(st) => &st.field(*index)?.schema,
+                        _ => return None,
+                    };
+                }
+                FieldPathKind::of_schema(current)
+            })
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| Error::IndexTypeMismatch(name.to_string()))?;
+
+        Ok(Self {
+            name: name.to_string(),
+            version,
+            paths,
+            kinds,
+        })
+    }
+
+    pub fn option(&self) -> IndexOption {
+        IndexOption {
+            version: self.version,
+            ..Default::default()
+        }
+    }
+
+    /// Build extractor from paths. Item with a missing or `None` value at
+    /// any path is not indexed, a value not of the path's kind is an error.
+    pub fn extractor(&self) -> Extractor {
+        let paths = self.paths.clone();
+        let kinds = self.kinds.clone();
+        Box::new(move |_pk, value| {
+            let st = DynamicStruct::from_slice(value)?;
+            let mut key = Key::new();
+            for (i, path) in paths.iter().enumerate() {
+                let mut value = st.get_path(path);
+                while let Some(DynamicValue::Optional(inner)) = value {
+                    value = inner.as_deref();
+                }
+                let value = match value {
+                    None => return Ok(vec![]),
+                    Some(value) => value,
+                };
+                match (kinds.get(i), value) {
+                    (None | Some(FieldPathKind::I64), DynamicValue::I64(v)) => key.append_i64(*v),
+                    (None | Some(FieldPathKind::F64), DynamicValue::F64(v)) => key.append_f64(*v),
+                    (None | Some(FieldPathKind::Bytes), DynamicValue::Bytes(v)) => {
+                        key.append_bytes(v)
+                    }
+                    (Some(FieldPathKind::Str), DynamicValue::Bytes(v)) => key
+                        .append_str(std::str::from_utf8(v).map_err(|_| vdb_value::Error::String)?),
+                    _ => return Err(vdb_key::Error::TypeMismatch.into()),
+                }
+            }
+            Ok(vec![key.into_bytes()])
+        })
+    }
+}
@@ -0,0 +1,181 @@
+use super::{expired_pk_condition, Extractor};
+use crate::{now_millis, Error};
+use rust_stemmers::{Algorithm, Stemmer};
+use std::collections::BTreeMap;
+use vdb_key::{Component, Key};
+
+/// Extract text to be indexed from pk and value
+pub type TextExtractor = Box<dyn Fn(&[u8], &[u8]) -> Result<String, Error> + Send + Sync>;
+
+/// Splits text into normalized terms: lowercased, split on non alphanumeric
+/// chars, optionally stemmed with the snowball english stemmer
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Tokenizer {
+    pub stemming: bool,
+}
+
+impl Tokenizer {
+    pub fn tokenize(&self, text: &str) -> Vec<String> {
+        let stemmer = self.stemming.then(|| Stemmer::create(Algorithm::English));
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                let word = word.to_lowercase();
+                match stemmer.as_ref() {
+                    Some(stemmer) => stemmer.stem(&word).into_owned(),
+                    None => word,
+                }
+            })
+            .collect()
+    }
+
+    /// config persisted with the index, postings are rebuilt when it changes
+    pub(crate) fn config(&self) -> i64 {
+        self.stemming as i64
+    }
+
+    /// Build index extractor, each distinct term becomes an index key
+    /// `(term, term frequency)`
+    pub fn extractor(self, text_extractor: TextExtractor) -> Extractor {
+        Box::new(move |pk, value| {
+            let text = text_extractor(pk, value)?;
+
+            let mut term_freqs = BTreeMap::<String, i64>::new();
+            for term in self.tokenize(text.as_str()) {
+                *term_freqs.entry(term).or_default() += 1;
+            }
+
+            Ok(term_freqs
+                .into_iter()
+                .map(|(term, freq)| {
+                    Key::from(&[Component::from(term.into_bytes()), Component::from(freq)][..])
+                        .into_bytes()
+                })
+                .collect())
+        })
+    }
+}
+
+/// Full text query, results are ranked by sum of matched term frequencies
+#[derive(Debug, Clone, PartialEq)]
+pub enum FullTextQuery {
+    /// match term, normalized with the index's tokenizer
+    Term(String),
+    /// match terms starting with prefix, prefix is only lowercased
+    Prefix(String),
+    And(Vec<FullTextQuery>),
+    Or(Vec<FullTextQuery>),
+}
+
+/// split posting ik into term and term frequency
+fn parse_posting(ik: &[u8]) -> Result<(Vec<u8>, i64), Error> {
+    match Key::load_from_bytes_unchecked(ik.to_vec())
+        .try_components()?
+        .as_slice()
+    {
+        [Component::Bytes(term), Component::I64(freq)] => Ok((term.clone(), *freq)),
+        _ => Err(vdb_key::Error::TypeMismatch.into()),
+    }
+}
+
+/// Inverted index of a full text index, one (term, pk, freq) row for each
+/// distinct term of each item, maintained on each entry change of the
+/// index, searches only read this table
+pub(crate) struct FullTextPostings {
+    pub table_name: String,
+    pub tokenizer: Tokenizer,
+}
+
+impl FullTextPostings {
+    pub fn create_table(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(&format!(
+            r#"
+            create table if not exists {postings_table} (
+              term blob,
+              pk blob,
+              freq integer,
+              primary key (term, pk)
+            ) WITHOUT ROWID;
+            "#,
+            postings_table = self.table_name,
+        ))?;
+        Ok(())
+    }
+
+    pub fn truncate(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(&format!(
+            r#"DELETE FROM {postings_table};"#,
+            postings_table = self.table_name,
+        ))?;
+        Ok(())
+    }
+
+    /// entry (ik, pk) added to index
+    pub fn add(&self, conn: &rusqlite::Connection, ik: &[u8], pk: &[u8]) -> Result<(), Error> {
+        let (term, freq) = parse_posting(ik)?;
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {postings_table} (term, pk, freq) VALUES (:term, :pk, :freq)"#,
+            postings_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":term": term,
+            ":pk": pk,
+            ":freq": freq,
+        })?;
+        Ok(())
+    }
+
+    /// entry (ik, pk) removed from index
+    pub fn remove(&self, conn: &rusqlite::Connection, ik: &[u8], pk: &[u8]) -> Result<(), Error> {
+        let (term, freq) = parse_posting(ik)?;
+        // a changed frequency may already have replaced the row
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"DELETE FROM {postings_table} WHERE term = :term AND pk = :pk AND freq = :freq"#,
+            postings_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":term": term,
+            ":pk": pk,
+            ":freq": freq,
+        })?;
+        Ok(())
+    }
+
+    /// term frequency sum for each pk with term, or terms starting with prefix,
+    /// skipping keys expired in `source_table`, the data table of the indexed table
+    pub fn postings(
+        &self,
+        conn: &rusqlite::Connection,
+        source_table: &str,
+        term: &str,
+        is_prefix: bool,
+    ) -> Result<BTreeMap<Vec<u8>, u32>, Error> {
+        let lower = term.as_bytes().to_vec();
+        let higher = if is_prefix {
+            // 0xff never appears in utf8 text
+            let mut higher = lower.clone();
+            higher.push(0xff);
+            higher
+        } else {
+            lower.clone()
+        };
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT pk, freq FROM {postings_table} WHERE term >= :lower AND term <= :higher AND NOT {expired}"#,
+            postings_table = self.table_name,
+            expired = expired_pk_condition(source_table),
+        ))?;
+        let mut rows = stmt.query(rusqlite::named_params! {
+            ":lower": lower,
+            ":higher": higher,
+            ":now": now_millis(),
+        })?;
+
+        let mut result = BTreeMap::<Vec<u8>, u32>::new();
+        while let Some(row) = rows.next()? {
+            let freq: i64 = row.get(1)?;
+            *result.entry(row.get(0)?).or_default() += freq as u32;
+        }
+        Ok(result)
+    }
+}
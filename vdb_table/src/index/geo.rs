@@ -0,0 +1,210 @@
+use super::Extractor;
+use crate::Error;
+use vdb_key::{Component, Key};
+
+/// Extract point to be indexed from pk and value, None means not indexed
+pub type GeoExtractor = Box<dyn Fn(&[u8], &[u8]) -> Result<Option<GeoPoint>, Error> + Send + Sync>;
+
+/// mean earth radius in meters
+const EARTH_RADIUS: f64 = 6_371_008.8;
+
+/// bits of each quantized coordinate, the interleaved cell id takes 62 bits,
+/// so it stays a non-negative i64
+const COORD_BITS: u32 = 31;
+
+/// max coarse cells a query is split into
+const MAX_QUERY_CELLS: u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoPoint {
+    pub lat: f64,
+    pub lon: f64,
+}
+
+/// Bounding box, min_lon greater than max_lon means the box crosses
+/// the antimeridian
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GeoBox {
+    pub min_lat: f64,
+    pub min_lon: f64,
+    pub max_lat: f64,
+    pub max_lon: f64,
+}
+
+impl GeoPoint {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Self { lat, lon }
+    }
+
+    /// haversine distance in meters
+    pub fn distance(&self, other: &GeoPoint) -> f64 {
+        let (lat1, lat2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lat = lat2 - lat1;
+        let d_lon = (other.lon - self.lon).to_radians();
+
+        let a = (d_lat / 2.).sin().powi(2) + lat1.cos() * lat2.cos() * (d_lon / 2.).sin().powi(2);
+        2. * EARTH_RADIUS * a.sqrt().min(1.).asin()
+    }
+
+    /// space filling curve cell id, interleaved bits of quantized lon and lat
+    pub fn cell(&self) -> i64 {
+        interleave(quantize(self.lon, 180.), quantize(self.lat, 90.)) as i64
+    }
+
+    /// index key, cell id followed by exact coordinates for refinement
+    pub(crate) fn to_ik(self) -> Vec<u8> {
+        Key::from(
+            &[
+                Component::from(self.cell()),
+                Component::from(self.lat),
+                Component::from(self.lon),
+            ][..],
+        )
+        .into_bytes()
+    }
+
+    pub(crate) fn from_ik(ik: Vec<u8>) -> Result<Self, Error> {
+        match Key::load_from_bytes_unchecked(ik)
+            .try_components()?
+            .as_slice()
+        {
+            [Component::I64(_), Component::F64(lat), Component::F64(lon)] => {
+                Ok(GeoPoint::new(*lat, *lon))
+            }
+            _ => Err(vdb_key::Error::TypeMismatch.into()),
+        }
+    }
+}
+
+impl GeoBox {
+    /// smallest box containing circle around center
+    pub fn around(center: GeoPoint, radius: f64) -> Self {
+        let d_lat = (radius / EARTH_RADIUS).to_degrees();
+        let min_lat = center.lat - d_lat;
+        let max_lat = center.lat + d_lat;
+
+        if min_lat <= -90. || max_lat >= 90. {
+            // circle covers a pole, all longitudes are involved
+            return GeoBox {
+                min_lat: min_lat.max(-90.),
+                min_lon: -180.,
+                max_lat: max_lat.min(90.),
+                max_lon: 180.,
+            };
+        }
+
+        // widest longitude span happens at latitude closest to the pole
+        let max_abs_lat = min_lat.abs().max(max_lat.abs()).to_radians();
+        let d_lon = d_lat / max_abs_lat.cos();
+        if d_lon >= 180. {
+            // wider than the whole circle of latitude
+            return GeoBox {
+                min_lat,
+                min_lon: -180.,
+                max_lat,
+                max_lon: 180.,
+            };
+        }
+
+        GeoBox {
+            min_lat,
+            min_lon: wrap_lon(center.lon - d_lon),
+            max_lat,
+            max_lon: wrap_lon(center.lon + d_lon),
+        }
+    }
+
+    pub fn contains(&self, point: &GeoPoint) -> bool {
+        let lat_ok = point.lat >= self.min_lat && point.lat <= self.max_lat;
+        let lon_ok = if self.min_lon <= self.max_lon {
+            point.lon >= self.min_lon && point.lon <= self.max_lon
+        } else {
+            point.lon >= self.min_lon || point.lon <= self.max_lon
+        };
+        lat_ok && lon_ok
+    }
+
+    /// cell id ranges `[lower, higher)` covering the box
+    pub(crate) fn cell_ranges(&self) -> Vec<(i64, i64)> {
+        let mut ranges = if self.min_lon <= self.max_lon {
+            cover(self.min_lat, self.min_lon, self.max_lat, self.max_lon)
+        } else {
+            let mut ranges = cover(self.min_lat, self.min_lon, self.max_lat, 180.);
+            ranges.extend(cover(self.min_lat, -180., self.max_lat, self.max_lon));
+            ranges
+        };
+
+        // merge adjacent ranges to reduce queries
+        ranges.sort_unstable();
+        let mut merged: Vec<(i64, i64)> = Vec::with_capacity(ranges.len());
+        for (lower, higher) in ranges {
+            match merged.last_mut() {
+                Some(last) if last.1 >= lower => last.1 = last.1.max(higher),
+                _ => merged.push((lower, higher)),
+            }
+        }
+        merged
+    }
+}
+
+/// Build index extractor from point extractor
+pub fn geo_extractor(point_extractor: GeoExtractor) -> Extractor {
+    Box::new(move |pk, value| {
+        Ok(point_extractor(pk, value)?
+            .into_iter()
+            .map(GeoPoint::to_ik)
+            .collect())
+    })
+}
+
+fn wrap_lon(lon: f64) -> f64 {
+    if lon < -180. {
+        lon + 360.
+    } else if lon > 180. {
+        lon - 360.
+    } else {
+        lon
+    }
+}
+
+/// map coordinate in [-range, range] to [0, 2^COORD_BITS)
+fn quantize(val: f64, range: f64) -> u64 {
+    let max = (1u64 << COORD_BITS) - 1;
+    let normalized = ((val + range) / (2. * range)).clamp(0., 1.);
+    ((normalized * (1u64 << COORD_BITS) as f64) as u64).min(max)
+}
+
+/// interleave lower COORD_BITS bits of x and y, x takes the higher bit of each pair
+fn interleave(x: u64, y: u64) -> u64 {
+    (0..COORD_BITS).fold(0u64, |accu, bit| {
+        accu | ((x >> bit) & 1) << (2 * bit + 1) | ((y >> bit) & 1) << (2 * bit)
+    })
+}
+
+/// cover box with coarse cells, each coarse cell is a contiguous range
+/// of fine cell ids
+fn cover(min_lat: f64, min_lon: f64, max_lat: f64, max_lon: f64) -> Vec<(i64, i64)> {
+    let (min_x, max_x) = (quantize(min_lon, 180.), quantize(max_lon, 180.));
+    let (min_y, max_y) = (quantize(min_lat, 90.), quantize(max_lat, 90.));
+
+    // coarsen until the cover is small enough
+    let mut shift = 0;
+    while shift < COORD_BITS
+        && ((max_x >> shift) - (min_x >> shift) + 1) * ((max_y >> shift) - (min_y >> shift) + 1)
+            > MAX_QUERY_CELLS
+    {
+        shift += 1;
+    }
+
+    let mut ranges = vec![];
+    for x in (min_x >> shift)..=(max_x >> shift) {
+        for y in (min_y >> shift)..=(max_y >> shift) {
+            let prefix = interleave(x, y);
+            ranges.push((
+                (prefix << (2 * shift)) as i64,
+                ((prefix + 1) << (2 * shift)) as i64,
+            ));
+        }
+    }
+    ranges
+}
@@ -0,0 +1,130 @@
+use crate::Error;
+use rusqlite::ToSql;
+
+/// History entries of an index, one (ik, pk, version) row for each index key
+/// of each version row, `until` is the version which replaced or deleted it,
+/// NULL while it is still the latest
+pub(crate) struct HistoryEntries {
+    pub table_name: String,
+}
+
+impl HistoryEntries {
+    /// returns true if the table did not exist before
+    pub fn create_table(&self, conn: &rusqlite::Connection) -> Result<bool, Error> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [self.table_name.as_str()],
+            |row| row.get(0),
+        )?;
+
+        conn.execute_batch(&format!(
+            r#"
+            create table if not exists {history_table} (
+              ik blob,
+              pk blob,
+              version integer,
+              until integer,
+              primary key (ik, pk, version)
+            ) WITHOUT ROWID;
+
+            create index if not exists idx_{history_table}_pk on {history_table} (pk, until);
+            "#,
+            history_table = self.table_name,
+        ))?;
+        Ok(!exists)
+    }
+
+    pub fn truncate(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(&format!(
+            r#"DELETE FROM {history_table};"#,
+            history_table = self.table_name,
+        ))?;
+        Ok(())
+    }
+
+    /// pk got index keys `iks` at `version`, entries of earlier versions are closed.
+    /// Replaying a version is idempotent, so backfill may overlap live writes
+    pub fn append(
+        &self,
+        conn: &rusqlite::Connection,
+        pk: &[u8],
+        iks: &[Vec<u8>],
+        version: i64,
+    ) -> Result<(), Error> {
+        self.close(conn, pk, version)?;
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {history_table} (ik, pk, version, until) VALUES (:ik, :pk, :version, NULL)"#,
+            history_table = self.table_name,
+        ))?;
+        for ik in iks.iter() {
+            stmt.execute(rusqlite::named_params! {
+                ":ik": ik,
+                ":pk": pk,
+                ":version": version,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// pk replaced or deleted at `version`
+    pub fn close(&self, conn: &rusqlite::Connection, pk: &[u8], version: i64) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"UPDATE {history_table} SET until = :version WHERE pk = :pk AND until IS NULL AND version < :version"#,
+            history_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":pk": pk,
+            ":version": version,
+        })?;
+        Ok(())
+    }
+
+    /// all (ik, pk, version) entries with ik in range, ordered by ik, pk and version
+    #[allow(clippy::type_complexity)]
+    pub fn versions(
+        &self,
+        conn: &rusqlite::Connection,
+        range_clause: &str,
+        range_params: &[(&str, &dyn ToSql)],
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>, i64)>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT ik, pk, version FROM {history_table} WHERE {range_clause} ORDER BY ik, pk, version"#,
+            history_table = self.table_name,
+            range_clause = range_clause,
+        ))?;
+        let mut rows = stmt.query(range_params)?;
+
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            entries.push((row.get(0)?, row.get(1)?, row.get(2)?));
+        }
+        Ok(entries)
+    }
+
+    /// (ik, pk) entries with ik in range which were latest at `version`
+    #[allow(clippy::type_complexity)]
+    pub fn as_of(
+        &self,
+        conn: &rusqlite::Connection,
+        range_clause: &str,
+        range_params: &[(&str, &dyn ToSql)],
+        version: i64,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT ik, pk FROM {history_table} WHERE {range_clause} AND version <= :as_of AND (until IS NULL OR until > :as_of) ORDER BY ik, pk"#,
+            history_table = self.table_name,
+            range_clause = range_clause,
+        ))?;
+
+        let mut params = range_params.to_vec();
+        params.push((":as_of", &version));
+        let mut rows = stmt.query(params.as_slice())?;
+
+        let mut entries = Vec::new();
+        while let Some(row) = rows.next()? {
+            entries.push((row.get(0)?, row.get(1)?));
+        }
+        Ok(entries)
+    }
+}
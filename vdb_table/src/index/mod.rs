@@ -1,18 +1,65 @@
+use crate::now_millis;
 use crate::{Error, TableUpdate};
 use rusqlite::ToSql;
+use std::collections::BTreeSet;
+use std::ops::{Bound, RangeBounds};
+use std::sync::Arc;
+
+mod field_path;
+pub use field_path::*;
+
+mod fulltext;
+pub use fulltext::*;
+
+mod geo;
+pub use geo::*;
+
+mod vector;
+pub use vector::*;
+
+mod aggregate;
+pub use aggregate::*;
+
+mod history;
+use history::HistoryEntries;
 
 pub type Extractor = Box<dyn Fn(&[u8], &[u8]) -> Result<Vec<Vec<u8>>, Error> + Send + Sync>;
 
 pub struct IndexOption {
     pub without_rowid: bool,
+    /// user supplied definition version, bump it when extractor logic
+    /// changes, then `Table::create_table` rebuilds the index
+    pub version: i64,
+    /// allow scan while the index is still being backfilled, results
+    /// may miss items not yet caught up
+    pub allow_read_while_building: bool,
+    /// also index every version row, not just the latest, see
+    /// `Index::history_versions` and `Index::as_of`
+    pub history: bool,
+}
+
+impl Default for IndexOption {
+    fn default() -> Self {
+        Self {
+            without_rowid: true,
+            version: 0,
+            allow_read_while_building: false,
+            history: false,
+        }
+    }
 }
 
 pub struct Index {
     pub name: String,
     data_table_name: String,
+    source_table_name: String,
     config_table_name: String,
     option: IndexOption,
     extractor: Extractor,
+    aggregate: Option<AggregateSummary>,
+    history: Option<HistoryEntries>,
+    fulltext: Option<FullTextPostings>,
+    vectors: Option<VectorEntries>,
 }
 
 impl Index {
@@ -22,18 +69,125 @@ impl Index {
         option: IndexOption,
         extractor: Extractor,
     ) -> Self {
-        let data_table_name = format!("{}_idx_{}_data", table_name, index_name);
-        let config_table_name = format!("{}_idx_{}_config", table_name, index_name);
+        let [data_table_name, config_table_name, _, history_table_name, _, _] =
+            Self::table_names(table_name, index_name);
+        let history = option.history.then_some(HistoryEntries {
+            table_name: history_table_name,
+        });
 
         Self {
             name: index_name.to_string(),
             data_table_name,
+            source_table_name: format!("{}_$_data", table_name),
             config_table_name,
             option,
             extractor,
+            aggregate: None,
+            history,
+            fulltext: None,
+            vectors: None,
         }
     }
 
+    /// Index which also keeps per group count, sum, min and max of
+    /// entries, see `aggregate_extractor` for entry layout
+    pub fn new_aggregate(
+        index_name: &str,
+        table_name: &str,
+        option: IndexOption,
+        extractor: AggregateExtractor,
+    ) -> Self {
+        let mut index = Self::new(
+            index_name,
+            table_name,
+            option,
+            aggregate_extractor(extractor),
+        );
+        let [_, _, aggregate_table_name, _, _, _] = Self::table_names(table_name, index_name);
+        index.aggregate = Some(AggregateSummary {
+            table_name: aggregate_table_name,
+        });
+        index
+    }
+
+    /// Index which also keeps an inverted index of terms of text returned
+    /// by text_extractor, see `Tokenizer::extractor` for entry layout
+    pub fn new_fulltext(
+        index_name: &str,
+        table_name: &str,
+        option: IndexOption,
+        tokenizer: Tokenizer,
+        text_extractor: TextExtractor,
+    ) -> Self {
+        let mut index = Self::new(
+            index_name,
+            table_name,
+            option,
+            tokenizer.extractor(text_extractor),
+        );
+        let [_, _, _, _, postings_table_name, _] = Self::table_names(table_name, index_name);
+        index.fulltext = Some(FullTextPostings {
+            table_name: postings_table_name,
+            tokenizer,
+        });
+        index
+    }
+
+    /// Index which keeps vectors returned by extractor by pk, entry ik is
+    /// the vector dimension
+    pub fn new_vector(
+        index_name: &str,
+        table_name: &str,
+        option: IndexOption,
+        extractor: VectorExtractor,
+    ) -> Self {
+        let extractor = Arc::new(extractor);
+        let mut index = Self::new(
+            index_name,
+            table_name,
+            option,
+            vector_extractor(extractor.clone()),
+        );
+        let [_, _, _, _, _, vector_table_name] = Self::table_names(table_name, index_name);
+        index.vectors = Some(VectorEntries {
+            table_name: vector_table_name,
+            extractor,
+        });
+        index
+    }
+
+    /// data, config, aggregate, history, full text postings and vector
+    /// table names of index `index_name` on `table_name`, the last four
+    /// only exist for some kinds of index
+    pub(crate) fn table_names(table_name: &str, index_name: &str) -> [String; 6] {
+        [
+            format!("{}_idx_{}_data", table_name, index_name),
+            format!("{}_idx_{}_config", table_name, index_name),
+            format!("{}_idx_{}_agg", table_name, index_name),
+            format!("{}_idx_{}_hist", table_name, index_name),
+            format!("{}_idx_{}_terms", table_name, index_name),
+            format!("{}_idx_{}_vec", table_name, index_name),
+        ]
+    }
+
+    /// All tables owned by this index
+    pub fn tables(&self) -> Vec<String> {
+        let mut tables = vec![self.data_table_name.clone(), self.config_table_name.clone()];
+        if let Some(aggregate) = self.aggregate.as_ref() {
+            tables.push(aggregate.table_name.clone());
+        }
+        if let Some(history) = self.history.as_ref() {
+            tables.push(history.table_name.clone());
+        }
+        if let Some(fulltext) = self.fulltext.as_ref() {
+            tables.push(fulltext.table_name.clone());
+        }
+        if let Some(vectors) = self.vectors.as_ref() {
+            tables.push(vectors.table_name.clone());
+        }
+        tables
+    }
+
     /// Create all required tables, then table will track
     /// each index's table and delete unused tables
     /// returns all tables created
@@ -67,10 +221,78 @@ impl Index {
             .as_str(),
         )?;
 
-        Ok(vec![
-            self.data_table_name.clone(),
-            self.config_table_name.clone(),
-        ])
+        if let Some(aggregate) = self.aggregate.as_ref() {
+            aggregate.create_table(conn)?;
+        }
+        if let Some(history) = self.history.as_ref() {
+            // history turned on for an existing index, earlier versions
+            // are only known by replaying the data table from the start
+            if history.create_table(conn)? && self.get_data_version(conn)?.is_some() {
+                self.truncate(conn)?;
+            }
+        }
+        if let Some(fulltext) = self.fulltext.as_ref() {
+            fulltext.create_table(conn)?;
+        }
+        if let Some(vectors) = self.vectors.as_ref() {
+            // index written when vectors were kept in ik
+            if vectors.create_table(conn)? && self.get_data_version(conn)?.is_some() {
+                self.truncate(conn)?;
+            }
+        }
+
+        Ok(self.tables())
+    }
+
+    /// Compare persisted definition version with the one in option, if they
+    /// differ, the index is truncated and needs a full rebuild. Tokenizer
+    /// config of full text index is compared the same way.
+    /// returns true if the index was truncated
+    pub fn sync_definition_version(&self, conn: &rusqlite::Connection) -> Result<bool, Error> {
+        // index created before definition version introduced is treated as version 0
+        let prev_version = self.get_definition_version(conn)?.unwrap_or_default();
+        let tokenizer_config = self
+            .fulltext
+            .as_ref()
+            .map(|fulltext| fulltext.tokenizer.config());
+        if prev_version == self.option.version
+            && self.get_tokenizer_config(conn)? == tokenizer_config
+        {
+            return Ok(false);
+        }
+
+        self.truncate(conn)?;
+        self.inner_save_definition_version(conn, self.option.version)?;
+        if let Some(config) = tokenizer_config {
+            self.inner_save_tokenizer_config(conn, config)?;
+        }
+        Ok(true)
+    }
+
+    /// Remove all index entries and synced data version, next catch up
+    /// rebuilds the index from the first version
+    pub fn truncate(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(&format!(
+            r#"
+            DELETE FROM {data_table};
+            DELETE FROM {config_table} WHERE key = 1;
+            "#,
+            data_table = self.data_table_name,
+            config_table = self.config_table_name,
+        ))?;
+        if let Some(aggregate) = self.aggregate.as_ref() {
+            aggregate.truncate(conn)?;
+        }
+        if let Some(history) = self.history.as_ref() {
+            history.truncate(conn)?;
+        }
+        if let Some(fulltext) = self.fulltext.as_ref() {
+            fulltext.truncate(conn)?;
+        }
+        if let Some(vectors) = self.vectors.as_ref() {
+            vectors.truncate(conn)?;
+        }
+        Ok(())
     }
 
     pub fn table_update(
@@ -78,16 +300,21 @@ impl Index {
         conn: &rusqlite::Connection,
         updates: &[TableUpdate],
     ) -> Result<(), Error> {
+        // read once per batch, the flag only changes by backfill
+        let building = self.is_building(conn)?;
         for update in updates.iter() {
             match update {
                 TableUpdate::Upsert(items) => {
                     for (key, value, version) in items.iter() {
-                        self.update(conn, key, value, *version)?;
+                        self.inner_update(conn, key, value, *version, building)?;
                     }
                 }
                 TableUpdate::Delete(keys) => {
-                    for key in keys {
-                        self.delete_by_pk(conn, key.0)?;
+                    for (key, version) in keys {
+                        self.delete_by_pk(conn, key)?;
+                        if let Some(history) = self.history.as_ref() {
+                            history.close(conn, key, *version)?;
+                        }
                     }
                 }
             }
@@ -101,9 +328,25 @@ impl Index {
         pk: &[u8],
         value: &[u8],
         version: i64,
+    ) -> Result<(), Error> {
+        let building = self.is_building(conn)?;
+        self.inner_update(conn, pk, value, version, building)
+    }
+
+    fn inner_update(
+        &self,
+        conn: &rusqlite::Connection,
+        pk: &[u8],
+        value: &[u8],
+        version: i64,
+        building: bool,
     ) -> Result<(), Error> {
         let prev_index_keys = self.inner_get_prev_keys(conn, pk)?;
-        let new_index_keys = (self.extractor)(pk, value)?;
+        let new_index_keys = match self.vectors.as_ref() {
+            // vector may change while its dimension ik stays
+            Some(vectors) => vectors.update(conn, pk, value)?,
+            None => self.extract(pk, value)?,
+        };
 
         let mut keys_to_delete = Vec::<&[u8]>::new();
         let mut keys_to_insert = Vec::<&[u8]>::new();
@@ -122,26 +365,247 @@ impl Index {
             keys_to_delete.push(index_key);
         }
 
-        self.inner_delete_iks(conn, keys_to_delete.as_slice())?;
+        self.inner_delete_iks(conn, keys_to_delete.as_slice(), pk)?;
         self.inner_insert_iks(conn, keys_to_insert.as_slice(), pk)?;
 
-        // store version to config table
-        self.inner_save_version(conn, version)?;
+        if let Some(history) = self.history.as_ref() {
+            history.append(conn, pk, new_index_keys.as_slice(), version)?;
+        }
+
+        // store version to config table, while building, the backfill
+        // owns synced version, otherwise it skips items not caught up
+        if !building {
+            self.inner_save_version(conn, version)?;
+        }
         Ok(())
     }
 
+    /// run extractor over pk and value, returns index keys
+    pub fn extract(&self, pk: &[u8], value: &[u8]) -> Result<Vec<Vec<u8>>, Error> {
+        (self.extractor)(pk, value)
+    }
+
     fn delete_by_pk(&self, conn: &rusqlite::Connection, pk: &[u8]) -> Result<(), Error> {
+        let prev_index_keys = self.inner_get_prev_keys(conn, pk)?;
+        let keys_to_delete = prev_index_keys
+            .iter()
+            .map(|k| k.as_slice())
+            .collect::<Vec<_>>();
+        if let Some(vectors) = self.vectors.as_ref() {
+            vectors.remove(conn, pk)?;
+        }
+        self.inner_delete_iks(conn, keys_to_delete.as_slice(), pk)
+    }
+
+    /// count entries with ik in range, or distinct pks if `distinct_pk`.
+    /// entries of expired keys are skipped
+    pub fn count<'a>(
+        &self,
+        conn: &rusqlite::Connection,
+        range: impl RangeBounds<&'a [u8]>,
+        distinct_pk: bool,
+    ) -> Result<i64, Error> {
+        self.check_readable(conn)?;
+
+        let (where_clause, mut params) = ik_range_clause(&range);
+        let now = now_millis();
+        params.push((":now", &now));
+
         let mut stmt = conn.prepare_cached(&format!(
-            r#"delete from {data_table} where pk = :pk"#,
-            data_table = self.data_table_name
+            r#"SELECT {count} FROM {data_table} WHERE {where_clause} AND NOT {expired}"#,
+            count = if distinct_pk {
+                "COUNT(DISTINCT pk)"
+            } else {
+                "COUNT(*)"
+            },
+            data_table = self.data_table_name,
+            where_clause = where_clause,
+            expired = expired_pk_condition(&self.source_table_name),
         ))?;
+        Ok(stmt.query_row(params.as_slice(), |row| row.get(0))?)
+    }
 
-        stmt.execute(rusqlite::named_params! {
-            ":pk": pk,
-        })?;
+    /// aggregated values of group, None if group has no entry, entries of
+    /// expired keys are skipped
+    pub fn aggregate(
+        &self,
+        conn: &rusqlite::Connection,
+        group: &[u8],
+    ) -> Result<Option<Aggregate>, Error> {
+        self.check_readable(conn)?;
+        let summary = self.aggregate_summary()?;
+        if self.expired_groups(conn)?.contains(group) {
+            return summary.compute(
+                conn,
+                &self.data_table_name,
+                group,
+                Some(&self.source_table_name),
+            );
+        }
+        summary.get(conn, group)
+    }
 
-        Ok(())
+    /// aggregated values of all groups, ordered by group key, entries of
+    /// expired keys are skipped
+    pub fn aggregate_groups(
+        &self,
+        conn: &rusqlite::Connection,
+    ) -> Result<Vec<(Vec<u8>, Aggregate)>, Error> {
+        self.check_readable(conn)?;
+        let summary = self.aggregate_summary()?;
+        let expired_groups = self.expired_groups(conn)?;
+
+        let mut groups = Vec::new();
+        for (group, aggregate) in summary.groups(conn)? {
+            if !expired_groups.contains(&group) {
+                groups.push((group, aggregate));
+            } else if let Some(aggregate) = summary.compute(
+                conn,
+                &self.data_table_name,
+                &group,
+                Some(&self.source_table_name),
+            )? {
+                groups.push((group, aggregate));
+            }
+        }
+        Ok(groups)
+    }
+
+    /// groups with entries of expired keys, their summary is recomputed
+    fn expired_groups(&self, conn: &rusqlite::Connection) -> Result<BTreeSet<Vec<u8>>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT ik FROM {data_table} WHERE {expired}"#,
+            data_table = self.data_table_name,
+            expired = expired_pk_condition(&self.source_table_name),
+        ))?;
+        let mut rows = stmt.query(rusqlite::named_params! { ":now": now_millis() })?;
+
+        let mut groups = BTreeSet::new();
+        while let Some(row) = rows.next()? {
+            let ik: Vec<u8> = row.get(0)?;
+            groups.insert(parse_entry(&ik)?.0);
+        }
+        Ok(groups)
+    }
+
+    /// whether every version row is indexed
+    pub fn is_history(&self) -> bool {
+        self.history.is_some()
+    }
+
+    /// (ik, pk, version) of every version row with ik in range, ordered by
+    /// ik, pk and version. latest entries of expired keys are skipped
+    #[allow(clippy::type_complexity)]
+    pub fn history_versions<'a>(
+        &self,
+        conn: &rusqlite::Connection,
+        range: impl RangeBounds<&'a [u8]>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>, i64)>, Error> {
+        self.check_readable(conn)?;
+        let (where_clause, mut params) = ik_range_clause(&range);
+        let now = now_millis();
+        params.push((":now", &now));
+        self.history_entries()?.versions(
+            conn,
+            history_where_clause(&where_clause, &self.source_table_name).as_str(),
+            params.as_slice(),
+        )
+    }
+
+    /// (ik, pk) entries with ik in range as the index was at data `version`,
+    /// latest entries of expired keys are skipped
+    #[allow(clippy::type_complexity)]
+    pub fn as_of<'a>(
+        &self,
+        conn: &rusqlite::Connection,
+        range: impl RangeBounds<&'a [u8]>,
+        version: i64,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Error> {
+        self.check_readable(conn)?;
+        let (where_clause, mut params) = ik_range_clause(&range);
+        let now = now_millis();
+        params.push((":now", &now));
+        self.history_entries()?.as_of(
+            conn,
+            history_where_clause(&where_clause, &self.source_table_name).as_str(),
+            params.as_slice(),
+            version,
+        )
+    }
+
+    fn history_entries(&self) -> Result<&HistoryEntries, Error> {
+        self.history
+            .as_ref()
+            .ok_or_else(|| Error::IndexTypeMismatch(self.name.clone()))
+    }
+
+    pub(crate) fn vector_entries(&self) -> Result<&VectorEntries, Error> {
+        self.vectors
+            .as_ref()
+            .ok_or_else(|| Error::IndexTypeMismatch(self.name.clone()))
+    }
+
+    pub(crate) fn fulltext_postings(&self) -> Result<&FullTextPostings, Error> {
+        self.fulltext
+            .as_ref()
+            .ok_or_else(|| Error::IndexTypeMismatch(self.name.clone()))
+    }
+
+    fn aggregate_summary(&self) -> Result<&AggregateSummary, Error> {
+        self.aggregate
+            .as_ref()
+            .ok_or_else(|| Error::IndexTypeMismatch(self.name.clone()))
+    }
+}
+
+/// history entries still latest for expired keys of `source_table` are skipped
+fn history_where_clause(ik_range_clause: &str, source_table: &str) -> String {
+    format!(
+        "{} AND NOT (until IS NULL AND {})",
+        ik_range_clause,
+        expired_pk_condition(source_table)
+    )
+}
+
+/// sql condition, true if `pk` is a key of `source_table`, the data table
+/// of the indexed table, whose latest value expired but is not purged yet.
+/// binds `:now`
+pub(crate) fn expired_pk_condition(source_table: &str) -> String {
+    format!(
+        r#"EXISTS (SELECT 1 FROM {source_table} AS source WHERE source.key = pk AND source.is_latest = 1 AND source.is_deleted <> 1 AND source.expire_at <= :now)"#,
+        source_table = source_table,
+    )
+}
+
+/// sql condition and named params restricting ik to range
+fn ik_range_clause<'r, 'a: 'r>(
+    range: &'r impl RangeBounds<&'a [u8]>,
+) -> (String, Vec<(&'static str, &'r dyn ToSql)>) {
+    let mut clauses = vec!["1 = 1"];
+    let mut params = Vec::<(&'static str, &dyn ToSql)>::new();
+    match range.start_bound() {
+        Bound::Included(ik) => {
+            clauses.push("ik >= :lower_ik");
+            params.push((":lower_ik", ik));
+        }
+        Bound::Excluded(ik) => {
+            clauses.push("ik > :lower_ik");
+            params.push((":lower_ik", ik));
+        }
+        Bound::Unbounded => {}
+    }
+    match range.end_bound() {
+        Bound::Included(ik) => {
+            clauses.push("ik <= :higher_ik");
+            params.push((":higher_ik", ik));
+        }
+        Bound::Excluded(ik) => {
+            clauses.push("ik < :higher_ik");
+            params.push((":higher_ik", ik));
+        }
+        Bound::Unbounded => {}
     }
+    (clauses.join(" AND "), params)
 }
 
 /// Scan related methods
@@ -211,12 +675,23 @@ impl Index {
         conn: &rusqlite::Connection,
         options: ScanOptions,
     ) -> Result<ScanResult, Error> {
+        self.check_readable(conn)?;
+
         let (where_clause, where_params) = options.where_clause();
 
+        // expired keys are skipped before LIMIT, so up to count live entries return
+        let mut conditions = vec![format!(
+            "NOT {}",
+            expired_pk_condition(&self.source_table_name)
+        )];
+        if !where_clause.is_empty() {
+            conditions.push(where_clause);
+        }
+
         let sql = format!(
             r#"SELECT ik, pk FROM {data_table} WHERE {where_clause} {order_clause} LIMIT :count"#,
             data_table = self.data_table_name,
-            where_clause = where_clause,
+            where_clause = conditions.join(" AND "),
             order_clause = options.order_by(),
         );
         let mut stmt = conn.prepare_cached(dbg!(&sql))?;
@@ -229,6 +704,8 @@ impl Index {
         // +1 to detect has_more
         let query_count = options.count + 1;
         params.push((":count", &query_count));
+        let now = now_millis();
+        params.push((":now", &now));
 
         let mut rows = stmt.query(params.as_slice())?;
 
@@ -246,6 +723,53 @@ impl Index {
 
         Ok(ScanResult { keys, has_more })
     }
+
+    /// iterate entries with `lower_ik <= ik < higher_ik` in index order,
+    /// entries of expired keys are skipped
+    pub fn scan_ik_range(
+        &self,
+        conn: &rusqlite::Connection,
+        lower_ik: &[u8],
+        higher_ik: &[u8],
+        mut f: impl FnMut(Vec<u8>, Vec<u8>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.check_readable(conn)?;
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT ik, pk FROM {data_table} WHERE ik >= :lower_ik AND ik < :higher_ik AND NOT {expired} ORDER BY ik, pk"#,
+            data_table = self.data_table_name,
+            expired = expired_pk_condition(&self.source_table_name),
+        ))?;
+
+        let mut rows = stmt.query(rusqlite::named_params! {
+            ":lower_ik": lower_ik,
+            ":higher_ik": higher_ik,
+            ":now": now_millis(),
+        })?;
+        while let Some(row) = rows.next()? {
+            f(row.get(0)?, row.get(1)?)?;
+        }
+
+        Ok(())
+    }
+
+    /// iterate all entries in index order
+    pub fn scan_all(
+        &self,
+        conn: &rusqlite::Connection,
+        f: impl FnMut(Vec<u8>, Vec<u8>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        self.check_readable(conn)?;
+        self.for_each_entry(conn, f)
+    }
+
+    /// index being backfilled is not readable unless explicitly allowed
+    pub(crate) fn check_readable(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        if !self.option.allow_read_while_building && self.is_building(conn)? {
+            return Err(Error::IndexBuilding(self.name.clone()));
+        }
+        Ok(())
+    }
 }
 
 impl Index {
@@ -290,21 +814,94 @@ impl Index {
                 ":ik": ik,
                 ":pk": pk,
             })?;
+
+            if let Some(aggregate) = self.aggregate.as_ref() {
+                aggregate.add(conn, ik)?;
+            }
+            if let Some(fulltext) = self.fulltext.as_ref() {
+                fulltext.add(conn, ik, pk)?;
+            }
         }
 
         Ok(())
     }
 
-    fn inner_delete_iks(&self, conn: &rusqlite::Connection, iks: &[&[u8]]) -> Result<(), Error> {
+    fn inner_delete_iks(
+        &self,
+        conn: &rusqlite::Connection,
+        iks: &[&[u8]],
+        pk: &[u8],
+    ) -> Result<(), Error> {
         let mut stmt = conn.prepare_cached(&format!(
-            r#"DELETE FROM {data_table} WHERE ik = :ik"#,
+            r#"DELETE FROM {data_table} WHERE ik = :ik AND pk = :pk"#,
             data_table = self.data_table_name
         ))?;
 
         for ik in iks.iter() {
-            stmt.execute(rusqlite::named_params! {
+            let deleted = stmt.execute(rusqlite::named_params! {
                 ":ik": ik,
+                ":pk": pk,
             })?;
+
+            if let Some(aggregate) = self.aggregate.as_ref() {
+                if deleted > 0 {
+                    aggregate.remove(conn, &self.data_table_name, ik)?;
+                }
+            }
+            if let Some(fulltext) = self.fulltext.as_ref() {
+                fulltext.remove(conn, ik, pk)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// recompute aggregate summary from entries, used by repair
+    pub(crate) fn rebuild_aggregate(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        if let Some(aggregate) = self.aggregate.as_ref() {
+            aggregate.rebuild(conn, &self.data_table_name)?;
+        }
+        Ok(())
+    }
+
+    /// insert (ik, pk) entries, used by repair
+    pub(crate) fn insert_entries(
+        &self,
+        conn: &rusqlite::Connection,
+        entries: &[(Vec<u8>, Vec<u8>)],
+    ) -> Result<(), Error> {
+        for (ik, pk) in entries.iter() {
+            self.inner_insert_iks(conn, &[ik.as_slice()], pk)?;
+        }
+        Ok(())
+    }
+
+    /// delete (ik, pk) entries, used by repair
+    pub(crate) fn delete_entries(
+        &self,
+        conn: &rusqlite::Connection,
+        entries: &[(Vec<u8>, Vec<u8>)],
+    ) -> Result<(), Error> {
+        for (ik, pk) in entries.iter() {
+            self.inner_delete_iks(conn, &[ik.as_slice()], pk)?;
+        }
+        Ok(())
+    }
+
+    /// iterate all (ik, pk) entries in index order, even if index is building
+    pub fn for_each_entry(
+        &self,
+        conn: &rusqlite::Connection,
+        mut f: impl FnMut(Vec<u8>, Vec<u8>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT ik, pk FROM {data_table} ORDER BY ik, pk"#,
+            data_table = self.data_table_name
+        ))?;
+
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            f(row.get(0)?, row.get(1)?)?;
         }
 
         Ok(())
@@ -323,6 +920,107 @@ impl Index {
         Ok(())
     }
 
+    pub(crate) fn save_data_version(
+        &self,
+        conn: &rusqlite::Connection,
+        version: i64,
+    ) -> Result<(), Error> {
+        self.inner_save_version(conn, version)
+    }
+
+    /// whether the index is being backfilled
+    pub fn is_building(&self, conn: &rusqlite::Connection) -> Result<bool, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT value FROM {config_table} WHERE key = 3"#,
+            config_table = self.config_table_name,
+        ))?;
+        let building: Option<i64> = no_row_to_none!(stmt.query_row([], |row| row.get(0)))?;
+        Ok(building.unwrap_or_default() != 0)
+    }
+
+    pub(crate) fn set_building(
+        &self,
+        conn: &rusqlite::Connection,
+        building: bool,
+    ) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {config_table} (key, value) VALUES ( 3, :building ) "#,
+            config_table = self.config_table_name,
+        ))?;
+
+        stmt.execute(rusqlite::named_params! {
+            ":building": building,
+        })?;
+
+        Ok(())
+    }
+
+    fn inner_save_definition_version(
+        &self,
+        conn: &rusqlite::Connection,
+        version: i64,
+    ) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {config_table} (key, value) VALUES ( 2, :version ) "#,
+            config_table = self.config_table_name,
+        ))?;
+
+        stmt.execute(rusqlite::named_params! {
+            ":version": version,
+        })?;
+
+        Ok(())
+    }
+
+    fn inner_save_tokenizer_config(
+        &self,
+        conn: &rusqlite::Connection,
+        config: i64,
+    ) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {config_table} (key, value) VALUES ( 4, :config ) "#,
+            config_table = self.config_table_name,
+        ))?;
+
+        stmt.execute(rusqlite::named_params! {
+            ":config": config,
+        })?;
+
+        Ok(())
+    }
+
+    /// get full text tokenizer config persisted in config table
+    fn get_tokenizer_config(&self, conn: &rusqlite::Connection) -> Result<Option<i64>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT value FROM {config_table} WHERE key = 4"#,
+            config_table = self.config_table_name,
+        ))?;
+        let config: Option<i64> = no_row_to_none!(stmt.query_row([], |row| row.get(0)))?;
+        Ok(config)
+    }
+
+    /// get index definition version persisted in config table
+    pub fn get_definition_version(
+        &self,
+        conn: &rusqlite::Connection,
+    ) -> Result<Option<i64>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT value FROM {config_table} WHERE key = 2"#,
+            config_table = self.config_table_name,
+        ))?;
+        let version: Option<i64> = no_row_to_none!(stmt.query_row([], |row| row.get(0)))?;
+        Ok(version)
+    }
+
+    /// count of (ik, pk) entries in index
+    pub fn entry_count(&self, conn: &rusqlite::Connection) -> Result<i64, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT COUNT(*) FROM {data_table}"#,
+            data_table = self.data_table_name,
+        ))?;
+        Ok(stmt.query_row([], |row| row.get(0))?)
+    }
+
     /// get index synced data version
     pub fn get_data_version(&self, conn: &rusqlite::Connection) -> Result<Option<i64>, Error> {
         let mut stmt = conn.prepare_cached(&format!(
@@ -0,0 +1,196 @@
+use super::{expired_pk_condition, Extractor};
+use crate::{now_millis, Error};
+use std::cmp::Ordering;
+use std::sync::Arc;
+use vdb_key::Key;
+
+/// Extract vector to be indexed from pk and value, None means not indexed
+pub type VectorExtractor =
+    Box<dyn Fn(&[u8], &[u8]) -> Result<Option<Vec<f64>>, Error> + Send + Sync>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VectorMetric {
+    /// 1 - cosine similarity
+    Cosine,
+    /// euclidean distance
+    L2,
+}
+
+impl VectorMetric {
+    /// distance between two vectors with same dimension
+    pub fn distance(&self, l: &[f64], r: &[f64]) -> f64 {
+        match self {
+            VectorMetric::Cosine => {
+                let dot = l.iter().zip(r).map(|(x, y)| x * y).sum::<f64>();
+                let norm = norm(l) * norm(r);
+                if norm == 0. {
+                    // zero vector has no direction, treat as orthogonal
+                    1.
+                } else {
+                    1. - dot / norm
+                }
+            }
+            VectorMetric::L2 => l
+                .iter()
+                .zip(r)
+                .map(|(x, y)| (x - y).powi(2))
+                .sum::<f64>()
+                .sqrt(),
+        }
+    }
+}
+
+fn norm(v: &[f64]) -> f64 {
+    v.iter().map(|x| x * x).sum::<f64>().sqrt()
+}
+
+/// Build index extractor, index key is the vector dimension, the vector
+/// itself is kept by `VectorEntries`
+pub(crate) fn vector_extractor(vector_extractor: Arc<VectorExtractor>) -> Extractor {
+    Box::new(move |pk, value| {
+        Ok(vector_extractor(pk, value)?
+            .into_iter()
+            .map(|vector| dimension_ik(vector.len()))
+            .collect())
+    })
+}
+
+fn dimension_ik(dimension: usize) -> Vec<u8> {
+    Key::from(dimension as i64).into_bytes()
+}
+
+/// f64 little endian bytes
+fn vector_to_blob(vector: &[f64]) -> Vec<u8> {
+    vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+}
+
+fn vector_from_blob(blob: &[u8]) -> Vec<f64> {
+    blob.chunks_exact(8)
+        .map(|chunk| f64::from_le_bytes(chunk.try_into().unwrap()))
+        .collect()
+}
+
+/// Vectors of a vector index by pk, kept out of the index key, so entries
+/// of the index data table stay small
+pub(crate) struct VectorEntries {
+    pub table_name: String,
+    pub extractor: Arc<VectorExtractor>,
+}
+
+impl VectorEntries {
+    /// returns true if the table did not exist
+    pub fn create_table(&self, conn: &rusqlite::Connection) -> Result<bool, Error> {
+        let exists: bool = conn.query_row(
+            "SELECT COUNT(*) > 0 FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [self.table_name.as_str()],
+            |row| row.get(0),
+        )?;
+
+        conn.execute_batch(&format!(
+            r#"
+            create table if not exists {vector_table} (
+              pk blob primary key,
+              vector blob
+            ) WITHOUT ROWID;
+            "#,
+            vector_table = self.table_name,
+        ))?;
+        Ok(!exists)
+    }
+
+    pub fn truncate(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(&format!(
+            r#"DELETE FROM {vector_table};"#,
+            vector_table = self.table_name,
+        ))?;
+        Ok(())
+    }
+
+    /// keep vector extracted from pk and value, returns index keys
+    pub fn update(
+        &self,
+        conn: &rusqlite::Connection,
+        pk: &[u8],
+        value: &[u8],
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let vector = match (self.extractor)(pk, value)? {
+            None => {
+                self.remove(conn, pk)?;
+                return Ok(vec![]);
+            }
+            Some(vector) => vector,
+        };
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {vector_table} (pk, vector) VALUES (:pk, :vector)"#,
+            vector_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":pk": pk,
+            ":vector": vector_to_blob(&vector),
+        })?;
+        Ok(vec![dimension_ik(vector.len())])
+    }
+
+    pub fn remove(&self, conn: &rusqlite::Connection, pk: &[u8]) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"DELETE FROM {vector_table} WHERE pk = :pk"#,
+            vector_table = self.table_name,
+        ))?;
+        stmt.execute(rusqlite::named_params! { ":pk": pk })?;
+        Ok(())
+    }
+
+    /// iterate pk and vector of all vectors with `dimension`, skipping keys
+    /// expired in `source_table`, the data table of the indexed table
+    pub fn scan(
+        &self,
+        conn: &rusqlite::Connection,
+        source_table: &str,
+        dimension: usize,
+        mut f: impl FnMut(Vec<u8>, Vec<f64>) -> Result<(), Error>,
+    ) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT pk, vector FROM {vector_table} WHERE length(vector) = :length AND NOT {expired}"#,
+            vector_table = self.table_name,
+            expired = expired_pk_condition(source_table),
+        ))?;
+        let mut rows = stmt.query(rusqlite::named_params! {
+            ":length": dimension * 8,
+            ":now": now_millis(),
+        })?;
+        while let Some(row) = rows.next()? {
+            let blob: Vec<u8> = row.get(1)?;
+            f(row.get(0)?, vector_from_blob(&blob))?;
+        }
+        Ok(())
+    }
+}
+
+/// Candidate in top k heap, ordered by distance then pk
+pub(crate) struct Neighbour {
+    pub distance: f64,
+    pub pk: Vec<u8>,
+}
+
+impl PartialEq for Neighbour {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Neighbour {}
+
+impl PartialOrd for Neighbour {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Neighbour {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.distance
+            .total_cmp(&other.distance)
+            .then_with(|| self.pk.cmp(&other.pk))
+    }
+}
@@ -0,0 +1,58 @@
+use crate::index::{Aggregate, AggregateExtractor, Index, IndexOption};
+use crate::{Error, Table};
+use rusqlite::Connection;
+use std::ops::RangeBounds;
+
+impl Table {
+    /// Append aggregate index, which keeps count, sum, min and max of
+    /// extracted value per group, updated on every write
+    pub fn append_aggregate_index(
+        &mut self,
+        name: &str,
+        option: IndexOption,
+        extractor: AggregateExtractor,
+    ) {
+        let index = Index::new_aggregate(name, self.table_name.as_str(), option, extractor);
+        self.indexes.push(index);
+    }
+
+    /// Count index entries with ik in range, expired keys are skipped
+    pub fn count_index<'a>(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        range: impl RangeBounds<&'a [u8]>,
+    ) -> Result<i64, Error> {
+        self.index_by_name(index_name)?.count(conn, range, false)
+    }
+
+    /// Count distinct primary keys of index entries with ik in range
+    pub fn count_distinct_pk<'a>(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        range: impl RangeBounds<&'a [u8]>,
+    ) -> Result<i64, Error> {
+        self.index_by_name(index_name)?.count(conn, range, true)
+    }
+
+    /// Aggregated values of group in aggregate index, expired keys are
+    /// skipped
+    pub fn aggregate(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        group: &[u8],
+    ) -> Result<Option<Aggregate>, Error> {
+        self.index_by_name(index_name)?.aggregate(conn, group)
+    }
+
+    /// Aggregated values of all groups in aggregate index, ordered by group key
+    pub fn aggregate_groups(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+    ) -> Result<Vec<(Vec<u8>, Aggregate)>, Error> {
+        self.index_by_name(index_name)?.aggregate_groups(conn)
+    }
+}
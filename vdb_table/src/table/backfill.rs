@@ -0,0 +1,135 @@
+use crate::index::Index;
+use crate::{Error, Table, TableUpdate};
+use rusqlite::Connection;
+
+/// Progress of an index backfill, reported after each chunk committed
+#[derive(Debug)]
+pub struct BackfillProgress<'a> {
+    pub index_name: &'a str,
+    /// data version the index synced to
+    pub synced_version: i64,
+    /// latest data version when this backfill run started
+    pub target_version: i64,
+    /// items processed in this run
+    pub processed: u64,
+    /// index caught up with data table, reads are allowed from now
+    pub finished: bool,
+}
+
+pub type BackfillProgressFn = Box<dyn Fn(&BackfillProgress<'_>)>;
+
+pub struct BackfillOption {
+    /// items processed and committed in one chunk
+    pub chunk_size: u32,
+    /// stop after this many chunks, the next `backfill_indexes` call
+    /// resumes from the synced version. None means run to end
+    pub max_chunks: Option<u32>,
+    pub progress: Option<BackfillProgressFn>,
+}
+
+impl Default for BackfillOption {
+    fn default() -> Self {
+        Self {
+            chunk_size: 1000,
+            max_chunks: None,
+            progress: None,
+        }
+    }
+}
+
+impl Table {
+    /// Catch up all indexes with data table in chunks. Each chunk is committed
+    /// together with index's synced version if conn is not inside a transaction,
+    /// so an interrupted backfill resumes where it stopped.
+    pub fn backfill_indexes(&self, conn: &Connection) -> Result<(), Error> {
+        for index in self.indexes.iter() {
+            self.backfill_index(conn, index)?;
+        }
+        Ok(())
+    }
+
+    pub(super) fn backfill_index(&self, conn: &Connection, index: &Index) -> Result<(), Error> {
+        let option = &self.backfill_option;
+        let target_version = self.max_version(conn)?;
+        let mut synced_version = index.get_data_version(conn)?.unwrap_or_default();
+
+        if !index.is_building(conn)? {
+            if synced_version >= target_version {
+                return Ok(());
+            }
+            index.set_building(conn, true)?;
+        }
+
+        let mut processed = 0u64;
+        let mut chunks = 0u32;
+        loop {
+            if matches!(option.max_chunks, Some(max_chunks) if chunks >= max_chunks) {
+                return Ok(());
+            }
+
+            // commit each chunk if we own the transaction, otherwise
+            // everything goes into caller's transaction
+            let trans = if conn.is_autocommit() {
+                Some(conn.unchecked_transaction()?)
+            } else {
+                None
+            };
+
+            // history index replays every version row, others only need latest
+            let count = self.inner_scan_from(
+                conn,
+                synced_version,
+                option.chunk_size as i64,
+                !index.is_history(),
+                |key, value, version| {
+                    match value {
+                        None => index.table_update(
+                            conn,
+                            &[TableUpdate::Delete(vec![(key.as_slice(), version)])],
+                        )?,
+                        Some(value) => index.table_update(
+                            conn,
+                            &[TableUpdate::Upsert(vec![(
+                                key.as_slice(),
+                                value.as_slice(),
+                                version,
+                            )])],
+                        )?,
+                    }
+                    synced_version = version;
+                    Ok(())
+                },
+            )?;
+
+            // a short chunk means no more items, writes happened during
+            // backfill maintained index themselves
+            let finished = count < option.chunk_size as u64;
+
+            index.save_data_version(conn, synced_version)?;
+            if finished {
+                index.set_building(conn, false)?;
+            }
+
+            if let Some(trans) = trans {
+                trans.commit()?;
+            }
+
+            processed += count;
+            chunks += 1;
+
+            if let Some(progress) = option.progress.as_ref() {
+                progress(&BackfillProgress {
+                    index_name: index.name.as_str(),
+                    synced_version,
+                    target_version,
+                    processed,
+                    finished,
+                });
+            }
+
+            if finished {
+                return Ok(());
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{Error, Table, TableEvent, TableItemEvent};
+use crate::{Error, Table, TableEvent, TableItemEvent, TableUpdate};
 
 impl Table {
     pub fn delete(&self, conn: &mut rusqlite::Connection, key: &[u8]) -> Result<i64, Error> {
@@ -8,18 +8,12 @@ impl Table {
             return Ok(0);
         }
 
-        trans.execute(
-            format!(
-                r#"insert into {table_name} (key, is_latest, is_deleted, value) values (:key, 1, 1, '')"#,
-                table_name = self.data_table()
-            )
-                .as_str(),
-            rusqlite::named_params! {
-                ":key": key
-            },
-        )?;
+        let last_version = self.insert_tombstone(&trans, key)?;
 
-        let last_version = trans.last_insert_rowid();
+        let updates = [TableUpdate::Delete(vec![(key, last_version)])];
+        for index in self.indexes.iter() {
+            index.table_update(&trans, &updates)?;
+        }
 
         trans.commit()?;
 
@@ -41,18 +35,12 @@ impl Table {
             return Ok(0);
         }
 
-        trans.execute(
-            format!(
-                r#"insert into {table_name} (key, is_latest, is_deleted, value) values (:key, 1, 1, '')"#,
-                table_name = self.data_table()
-            )
-                .as_str(),
-            rusqlite::named_params! {
-                ":key": key
-            },
-        )?;
+        let last_version = self.insert_tombstone(&trans, &key)?;
 
-        let last_version = trans.last_insert_rowid();
+        let updates = [TableUpdate::Delete(vec![(key.as_slice(), last_version)])];
+        for index in self.indexes.iter() {
+            index.table_update(&trans, &updates)?;
+        }
 
         trans.commit()?;
 
@@ -67,4 +55,24 @@ impl Table {
 
         Ok(last_version)
     }
+
+    /// write tombstone as latest row of key, returns its version
+    pub(super) fn insert_tombstone(
+        &self,
+        trans: &rusqlite::Connection,
+        key: &[u8],
+    ) -> Result<i64, Error> {
+        trans.execute(
+            format!(
+                r#"insert into {table_name} (key, is_latest, is_deleted, value) values (:key, 1, 1, '')"#,
+                table_name = self.data_table()
+            )
+            .as_str(),
+            rusqlite::named_params! {
+                ":key": key
+            },
+        )?;
+
+        Ok(trans.last_insert_rowid())
+    }
 }
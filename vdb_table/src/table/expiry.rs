@@ -0,0 +1,98 @@
+use crate::{Error, Table, TableEvent, TableItemEvent, TableUpdate};
+use rusqlite::Connection;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Current unix time in milliseconds, the unit of expiry time
+pub fn now_millis() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+impl Table {
+    /// Insert key value which expires at `expire_at` unix millis, expired
+    /// key reads as absent until `purge_expired` deletes it
+    pub fn insert_with_expiry(
+        &self,
+        conn: &mut Connection,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expire_at: i64,
+    ) -> Result<i64, Error> {
+        self.insert_one(conn, key, value, Some(expire_at))
+    }
+
+    /// Insert key value which expires after `ttl`
+    pub fn insert_with_ttl(
+        &self,
+        conn: &mut Connection,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        ttl: Duration,
+    ) -> Result<i64, Error> {
+        let expire_at = now_millis().saturating_add(ttl.as_millis() as i64);
+        self.insert_with_expiry(conn, key, value, expire_at)
+    }
+
+    /// Expiry time of key's latest value, None if key is absent or never expires
+    pub fn get_expiry(&self, conn: &Connection, key: &[u8]) -> Result<Option<i64>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT expire_at FROM {table_name} WHERE key = :key AND is_latest = 1 AND is_deleted <> 1"#,
+            table_name = self.data_table(),
+        ))?;
+        let expire_at: Option<Option<i64>> = no_row_to_none!(stmt
+            .query_row(rusqlite::named_params! { ":key": key }, |row| {
+                row.get(0)
+            }))?;
+        Ok(expire_at.flatten())
+    }
+
+    /// Delete at most `limit` expired keys by writing tombstones, indexes and
+    /// observers see them as normal deletes. returns count of purged keys
+    pub fn purge_expired(&self, conn: &mut Connection, limit: u32) -> Result<u64, Error> {
+        let trans = conn.transaction()?;
+
+        let mut expired = Vec::<(Vec<u8>, Vec<u8>, i64)>::new();
+        {
+            let mut stmt = trans.prepare_cached(&format!(
+                r#"SELECT key, value, rowid FROM {table_name} WHERE is_latest = 1 AND is_deleted <> 1 AND expire_at <= :now ORDER BY expire_at LIMIT :limit"#,
+                table_name = self.data_table(),
+            ))?;
+            let mut rows = stmt.query(rusqlite::named_params! {
+                ":now": now_millis(),
+                ":limit": limit,
+            })?;
+            while let Some(row) = rows.next()? {
+                expired.push((row.get(0)?, row.get(1)?, row.get(2)?));
+            }
+        }
+
+        let mut data_events = Vec::<TableItemEvent>::with_capacity(expired.len());
+        for (key, value, version) in expired.into_iter() {
+            self.update_last_to_not_latest_with_version(&trans, &key, version)?;
+            let last_version = self.insert_tombstone(&trans, &key)?;
+
+            let updates = [TableUpdate::Delete(vec![(key.as_slice(), last_version)])];
+            for index in self.indexes.iter() {
+                index.table_update(&trans, &updates)?;
+            }
+
+            data_events.push(TableItemEvent {
+                key,
+                from: Some((value, version)),
+                to: None,
+            });
+        }
+
+        trans.commit()?;
+
+        if !data_events.is_empty() {
+            self.observers
+                .iter()
+                .for_each(|o| o(TableEvent::DataUpdates(data_events.as_slice())));
+        }
+
+        Ok(data_events.len() as u64)
+    }
+}
@@ -0,0 +1,104 @@
+use crate::index::{FullTextPostings, FullTextQuery, Index, IndexOption, TextExtractor, Tokenizer};
+use crate::{Error, Table};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+
+impl Table {
+    /// Append full text index over text returned by text_extractor, it is
+    /// maintained like other indexes, one entry per distinct term. Tokenizer
+    /// is persisted with the index, changing it rebuilds the index
+    pub fn append_fulltext_index(
+        &mut self,
+        name: &str,
+        option: IndexOption,
+        tokenizer: Tokenizer,
+        text_extractor: TextExtractor,
+    ) {
+        let index = Index::new_fulltext(
+            name,
+            self.table_name.as_str(),
+            option,
+            tokenizer,
+            text_extractor,
+        );
+        self.indexes.push(index);
+    }
+
+    /// Search full text index, returns at most `count` primary keys
+    /// with score, ordered by score desc
+    pub fn search_fulltext(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        query: &FullTextQuery,
+        count: u32,
+    ) -> Result<Vec<(Vec<u8>, u32)>, Error> {
+        let index = self.index_by_name(index_name)?;
+        index.check_readable(conn)?;
+
+        let source_table = self.data_table();
+        let mut results = Vec::from_iter(search(
+            conn,
+            index.fulltext_postings()?,
+            &source_table,
+            query,
+        )?);
+        results.sort_by(|l, r| r.1.cmp(&l.1).then_with(|| l.0.cmp(&r.0)));
+        results.truncate(count as usize);
+        Ok(results)
+    }
+}
+
+/// returns matched pk and score, expired keys are skipped
+fn search(
+    conn: &Connection,
+    postings: &FullTextPostings,
+    source_table: &str,
+    query: &FullTextQuery,
+) -> Result<BTreeMap<Vec<u8>, u32>, Error> {
+    match query {
+        FullTextQuery::Term(text) => {
+            // text may be split into multiple terms, all of them must match
+            let mut result = None;
+            for term in postings.tokenizer.tokenize(text) {
+                let matched = postings.postings(conn, source_table, term.as_str(), false)?;
+                result = Some(intersect(result, matched));
+            }
+            Ok(result.unwrap_or_default())
+        }
+        FullTextQuery::Prefix(prefix) => {
+            postings.postings(conn, source_table, &prefix.to_lowercase(), true)
+        }
+        FullTextQuery::And(queries) => {
+            let mut result = None;
+            for query in queries {
+                let matched = search(conn, postings, source_table, query)?;
+                result = Some(intersect(result, matched));
+            }
+            Ok(result.unwrap_or_default())
+        }
+        FullTextQuery::Or(queries) => {
+            let mut result = BTreeMap::new();
+            for query in queries {
+                for (pk, score) in search(conn, postings, source_table, query)? {
+                    *result.entry(pk).or_default() += score;
+                }
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// keep pks in both, scores are summed. None prev means nothing intersected yet
+fn intersect(
+    prev: Option<BTreeMap<Vec<u8>, u32>>,
+    matched: BTreeMap<Vec<u8>, u32>,
+) -> BTreeMap<Vec<u8>, u32> {
+    match prev {
+        None => matched,
+        Some(prev) => prev
+            .into_iter()
+            .filter_map(|(pk, score)| matched.get(&pk).map(|s| (pk, score + s)))
+            .collect(),
+    }
+}
@@ -0,0 +1,83 @@
+use crate::index::{geo_extractor, GeoBox, GeoExtractor, GeoPoint, Index, IndexOption};
+use crate::{Error, Table};
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use vdb_key::Key;
+
+impl Table {
+    /// Append geospatial index over point returned by point_extractor
+    pub fn append_geo_index(
+        &mut self,
+        name: &str,
+        option: IndexOption,
+        point_extractor: GeoExtractor,
+    ) {
+        self.append_index_with_option(name, option, geo_extractor(point_extractor));
+    }
+
+    /// Primary keys with point inside the box
+    pub fn geo_within_box(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        geo_box: &GeoBox,
+    ) -> Result<Vec<Vec<u8>>, Error> {
+        let index = self.index_by_name(index_name)?;
+        let mut pks = Vec::new();
+        for_each_point(conn, index, geo_box, |pk, point| {
+            if geo_box.contains(&point) {
+                pks.push(pk);
+            }
+        })?;
+        pks.sort();
+        pks.dedup();
+        Ok(pks)
+    }
+
+    /// Primary keys with point within `radius` meters of center, ordered
+    /// by distance
+    pub fn geo_within_radius(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        center: GeoPoint,
+        radius: f64,
+    ) -> Result<Vec<(Vec<u8>, f64)>, Error> {
+        let index = self.index_by_name(index_name)?;
+
+        // one pk may have multiple points, keep the nearest
+        let mut nearest = BTreeMap::<Vec<u8>, f64>::new();
+        for_each_point(conn, index, &GeoBox::around(center, radius), |pk, point| {
+            let distance = center.distance(&point);
+            if distance <= radius {
+                let entry = nearest.entry(pk).or_insert(distance);
+                *entry = entry.min(distance);
+            }
+        })?;
+
+        let mut result = Vec::from_iter(nearest);
+        result.sort_by(|l, r| l.1.total_cmp(&r.1).then_with(|| l.0.cmp(&r.0)));
+        Ok(result)
+    }
+}
+
+/// candidate points in cells covering the box, not refined
+fn for_each_point(
+    conn: &Connection,
+    index: &Index,
+    geo_box: &GeoBox,
+    mut f: impl FnMut(Vec<u8>, GeoPoint),
+) -> Result<(), Error> {
+    for (lower, higher) in geo_box.cell_ranges() {
+        index.scan_ik_range(
+            conn,
+            &Key::from(lower).into_bytes(),
+            &Key::from(higher).into_bytes(),
+            |ik, pk| {
+                f(pk, GeoPoint::from_ik(ik)?);
+                Ok(())
+            },
+        )?;
+    }
+    Ok(())
+}
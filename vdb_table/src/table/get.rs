@@ -1,7 +1,8 @@
+use super::now_millis;
 use crate::{Error, Table};
 
 impl Table {
-    /// Get latest value
+    /// Get latest value, expired value is treated as absent
     pub fn get(
         &self,
         conn: &rusqlite::Connection,
@@ -9,14 +10,15 @@ impl Table {
     ) -> Result<Option<(Vec<u8>, i64)>, Error> {
         let mut stmt = conn.prepare_cached(
             format!(
-                r#"select value, rowid from {table_name} where key = :key and is_latest = 1 and is_deleted <> 1"#,
+                r#"select value, rowid from {table_name} where key = :key and is_latest = 1 and is_deleted <> 1 and (expire_at is null or expire_at > :now)"#,
                 table_name = self.data_table()
             ).as_str(),
         )?;
 
         no_row_to_none!(stmt.query_row(
             rusqlite::named_params! {
-                ":key": key
+                ":key": key,
+                ":now": now_millis(),
             },
             |r| Ok((r.get(0)?, r.get(1)?)),
         ))
@@ -0,0 +1,33 @@
+use crate::{Error, Table};
+use rusqlite::Connection;
+use std::ops::RangeBounds;
+
+impl Table {
+    /// Every version row which had ik in range, as (ik, pk, version),
+    /// only for index appended with `IndexOption::history`. The latest
+    /// version of expired keys is skipped
+    #[allow(clippy::type_complexity)]
+    pub fn index_history<'a>(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        range: impl RangeBounds<&'a [u8]>,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>, i64)>, Error> {
+        self.index_by_name(index_name)?
+            .history_versions(conn, range)
+    }
+
+    /// (ik, pk) entries with ik in range as of data `version`, only for
+    /// index appended with `IndexOption::history`. The latest version of
+    /// expired keys is skipped
+    #[allow(clippy::type_complexity)]
+    pub fn index_as_of<'a>(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        range: impl RangeBounds<&'a [u8]>,
+        version: i64,
+    ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Error> {
+        self.index_by_name(index_name)?.as_of(conn, range, version)
+    }
+}
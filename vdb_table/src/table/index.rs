@@ -2,8 +2,81 @@ use crate::index::{Index, ScanKey, ScanOptions, ScanOrder};
 use crate::{Error, Table};
 use rusqlite::Connection;
 
+/// Index status returned by `Table::list_indexes`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexInfo {
+    pub name: String,
+    pub tables: Vec<String>,
+    /// data version the index synced to, None if never synced
+    pub synced_version: Option<i64>,
+    /// how many versions the index is behind data table
+    pub lag: i64,
+    pub entry_count: i64,
+    pub building: bool,
+}
+
 impl Table {
-    /// Get all index key and relative primary key pairs
+    /// List status of all appended indexes
+    pub fn list_indexes(&self, conn: &Connection) -> Result<Vec<IndexInfo>, Error> {
+        let max_version = self.max_version(conn)?;
+
+        let mut infos = Vec::with_capacity(self.indexes.len());
+        for index in self.indexes.iter() {
+            let synced_version = index.get_data_version(conn)?;
+            infos.push(IndexInfo {
+                name: index.name.clone(),
+                tables: index.tables(),
+                synced_version,
+                lag: max_version - synced_version.unwrap_or_default(),
+                entry_count: index.entry_count(conn)?,
+                building: index.is_building(conn)?,
+            });
+        }
+        Ok(infos)
+    }
+
+    /// Drop index tables and unregister the index. Also works for index
+    /// not appended, e.g. tables kept by create_table for unregistered index
+    pub fn drop_index(&mut self, conn: &Connection, index_name: &str) -> Result<(), Error> {
+        self.indexes.retain(|index| !index.name.eq(index_name));
+        self.field_path_index_defs
+            .retain(|def| !def.name.eq(index_name));
+
+        let index_tables = Index::table_names(self.table_name.as_str(), index_name);
+        self.drop_associated_tables(conn, &index_tables);
+
+        let tables = self
+            .load_associated_tables(conn)?
+            .into_iter()
+            .filter(|t| !index_tables.contains(t))
+            .collect::<Vec<_>>();
+        self.save_associated_tables(conn, tables)?;
+
+        let mut defs = self.load_field_path_index_defs(conn)?;
+        defs.retain(|def| !def.name.eq(index_name));
+        self.save_field_path_index_defs(conn, defs)?;
+
+        Ok(())
+    }
+
+    /// Remove all entries of index, the index stays building until
+    /// `backfill_indexes` catches it up
+    pub fn truncate_index(&self, conn: &Connection, index_name: &str) -> Result<(), Error> {
+        let index = self.index_by_name(index_name)?;
+        index.truncate(conn)?;
+        index.set_building(conn, true)?;
+        Ok(())
+    }
+
+    /// Truncate index and backfill it from the first version
+    pub fn rebuild_index(&self, conn: &Connection, index_name: &str) -> Result<(), Error> {
+        self.truncate_index(conn, index_name)?;
+        self.backfill_index(conn, self.index_by_name(index_name)?)
+    }
+
+    /// Get all index key and relative primary key pairs, entries of
+    /// expired keys are skipped, so it may return less than count
+    #[allow(clippy::type_complexity)]
     pub fn get_by_index(
         &self,
         conn: &Connection,
@@ -11,32 +84,30 @@ impl Table {
         key: &[u8],
         count: u32,
     ) -> Result<Vec<(Vec<u8>, Vec<u8>)>, Error> {
-        let index = match self.get_index_by_name(index_name) {
-            None => return Err(Error::IndexMissing(index_name.to_string())),
-            Some(index) => index,
-        };
-        let scan_result = index.scan(
-            conn,
-            ScanOptions {
-                lower_key: Some(ScanKey {
-                    ik: key,
-                    pk: b"",
-                    inclusive: true,
-                }),
-                higher_key: None,
-                count,
-                order: ScanOrder::Asc,
-            },
-        )?;
-        Ok(scan_result.keys)
+        let index = self.index_by_name(index_name)?;
+        Ok(index
+            .scan(
+                conn,
+                ScanOptions {
+                    lower_key: Some(ScanKey {
+                        ik: key,
+                        pk: b"",
+                        inclusive: true,
+                    }),
+                    higher_key: None,
+                    count,
+                    order: ScanOrder::Asc,
+                },
+            )?
+            .keys)
     }
 
-    fn get_index_by_name(&self, name: &str) -> Option<&Index> {
-        for index in self.indexes.iter() {
-            if index.name.eq(name) {
-                return Some(index);
-            }
-        }
-        None
+    pub(super) fn get_index_by_name(&self, name: &str) -> Option<&Index> {
+        self.indexes.iter().find(|index| index.name.eq(name))
+    }
+
+    pub(super) fn index_by_name(&self, name: &str) -> Result<&Index, Error> {
+        self.get_index_by_name(name)
+            .ok_or_else(|| Error::IndexMissing(name.to_string()))
     }
 }
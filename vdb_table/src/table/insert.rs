@@ -6,11 +6,22 @@ impl Table {
         conn: &mut rusqlite::Connection,
         key: Vec<u8>,
         value: Vec<u8>,
+    ) -> Result<i64, Error> {
+        self.insert_one(conn, key, value, None)
+    }
+
+    /// insert key value expiring at `expire_at` unix millis, None never expires
+    pub(super) fn insert_one(
+        &self,
+        conn: &mut rusqlite::Connection,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expire_at: Option<i64>,
     ) -> Result<i64, Error> {
         let trans = conn.transaction()?;
 
         let mut table_events = Vec::<TableItemEvent>::new();
-        let (v, event) = self.inner_insert(&trans, key, value)?;
+        let (v, event) = self.inner_insert_with_expiry(&trans, key, value, expire_at)?;
         table_events.push(event);
 
         trans.commit()?;
@@ -55,15 +66,25 @@ impl Table {
         key: Vec<u8>,
         value: Vec<u8>,
     ) -> Result<(i64, TableItemEvent), Error> {
-        let last_value_and_v = if let Some(last_value) = self.get(&trans, &key)? {
-            self.update_last_to_not_latest(&trans, &key)?;
-            Some(last_value)
-        } else {
-            None
-        };
+        self.inner_insert_with_expiry(trans, key, value, None)
+    }
+
+    /// insert key value expiring at `expire_at` unix millis into table,
+    /// also updates attached indexes
+    pub fn inner_insert_with_expiry(
+        &self,
+        trans: &rusqlite::Connection,
+        key: Vec<u8>,
+        value: Vec<u8>,
+        expire_at: Option<i64>,
+    ) -> Result<(i64, TableItemEvent), Error> {
+        let last_value_and_v = self.get(trans, &key)?;
+        // latest row may be a tombstone or expired, which get treats as absent
+        self.update_last_to_not_latest(trans, &key)?;
+
         let mut stmt = trans.prepare_cached(
             format!(
-                r#"INSERT INTO {table_name} (key, is_latest, is_deleted, value) VALUES (:key, 1, 0, :value)"#,
+                r#"INSERT INTO {table_name} (key, is_latest, is_deleted, value, expire_at) VALUES (:key, 1, 0, :value, :expire_at)"#,
                 table_name = self.data_table(),
             )
                 .as_str(),
@@ -72,6 +93,7 @@ impl Table {
         stmt.execute(rusqlite::named_params! {
             ":key": key,
             ":value": value,
+            ":expire_at": expire_at,
         })?;
         drop(stmt);
 
@@ -85,14 +107,14 @@ impl Table {
 
         // also update all indexes
         for index in self.indexes.iter() {
-            index.table_update(&trans, &updates)?;
+            index.table_update(trans, &updates)?;
         }
 
         Ok((
             v,
             TableItemEvent {
                 key,
-                from: last_value_and_v.map(|(value, version)| (value, version.clone())),
+                from: last_value_and_v,
                 to: Some((value, v)),
             },
         ))
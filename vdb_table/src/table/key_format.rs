@@ -0,0 +1,153 @@
+use crate::{Error, Table};
+use rusqlite::Connection;
+use vdb_key::{Key, KEY_FORMAT_VERSION};
+
+impl Table {
+    /// Key format version the table's keys are written in, tables created
+    /// before it was recorded are version 0
+    pub fn key_format_version(&self, conn: &Connection) -> Result<i64, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT value FROM {config_table} WHERE key = 4"#,
+            config_table = self.conf_table(),
+        ))?;
+        let version: Option<i64> = no_row_to_none!(stmt.query_row([], |row| row.get(0)))?;
+        Ok(version.unwrap_or_default())
+    }
+
+    /// Bring table written by older key format to `KEY_FORMAT_VERSION`.
+    /// Data keys are re-encoded if they are `Key` bytes, see
+    /// `set_key_encoded_pk`, index tables are truncated and rebuilt by
+    /// the following backfill, so extractors re-encode index keys. Kept
+    /// tables of unregistered indexes are truncated too, and rebuilt once
+    /// the index is appended again.
+    /// A non-empty table whose pk encoding is not declared is left in
+    /// its format, the version is not bumped so a later run migrates it.
+    pub(super) fn migrate_key_format(&self, conn: &Connection) -> Result<(), Error> {
+        let version = self.key_format_version(conn)?;
+        if version >= KEY_FORMAT_VERSION {
+            return Ok(());
+        }
+
+        let key_encoded_pk = match self.key_encoded_pk {
+            Some(key_encoded_pk) => key_encoded_pk,
+            None if self.is_empty(conn)? => false,
+            None => {
+                log::warn!(
+                    "table {} is in key format {}, not migrated to {} until set_key_encoded_pk is called",
+                    self.table_name,
+                    version,
+                    KEY_FORMAT_VERSION
+                );
+                return Ok(());
+            }
+        };
+
+        log::info!(
+            "migrate table {} from key format {} to {}",
+            self.table_name,
+            version,
+            KEY_FORMAT_VERSION
+        );
+
+        let trans = if conn.is_autocommit() {
+            Some(conn.unchecked_transaction()?)
+        } else {
+            None
+        };
+
+        if key_encoded_pk {
+            self.reencode_data_keys(conn, version)?;
+        }
+
+        for index in self.indexes.iter() {
+            index.truncate(conn)?;
+            index.set_building(conn, true)?;
+        }
+        self.reset_unregistered_index_tables(conn)?;
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {config_table} (key, value) VALUES ( 4, :version ) "#,
+            config_table = self.conf_table(),
+        ))?;
+        stmt.execute(rusqlite::named_params! {
+            ":version": KEY_FORMAT_VERSION,
+        })?;
+        drop(stmt);
+
+        if let Some(trans) = trans {
+            trans.commit()?;
+        }
+        Ok(())
+    }
+
+    /// truncate tracked tables of indexes not appended, their config is
+    /// marked building without synced version, like `Index::truncate`
+    fn reset_unregistered_index_tables(&self, conn: &Connection) -> Result<(), Error> {
+        let registered = self
+            .indexes
+            .iter()
+            .flat_map(|index| index.tables())
+            .collect::<Vec<_>>();
+
+        for table in self.load_associated_tables(conn)? {
+            if registered.contains(&table) {
+                continue;
+            }
+            if table.ends_with("_config") {
+                conn.execute_batch(&format!(
+                    r#"
+                    DELETE FROM {config_table} WHERE key = 1;
+                    INSERT OR REPLACE INTO {config_table} (key, value) VALUES ( 3, 1 );
+                    "#,
+                    config_table = table,
+                ))?;
+            } else {
+                conn.execute_batch(&format!(r#"DELETE FROM {table_name};"#, table_name = table,))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn is_empty(&self, conn: &Connection) -> Result<bool, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT NOT EXISTS (SELECT 1 FROM {table_name})"#,
+            table_name = self.data_table(),
+        ))?;
+        Ok(stmt.query_row([], |row| row.get(0))?)
+    }
+
+    fn reencode_data_keys(&self, conn: &Connection, version: i64) -> Result<(), Error> {
+        // re-encoded key may collide with a not yet re-encoded one,
+        // unique index is recreated once all keys are done
+        conn.execute_batch(&format!(
+            r#"DROP INDEX IF EXISTS idx_{table_name}_key_latest"#,
+            table_name = self.data_table(),
+        ))?;
+
+        let mut rows = Vec::<(i64, Vec<u8>)>::new();
+        {
+            let mut stmt = conn.prepare(&format!(
+                r#"SELECT rowid, key FROM {table_name}"#,
+                table_name = self.data_table(),
+            ))?;
+            let mut query = stmt.query([])?;
+            while let Some(row) = query.next()? {
+                rows.push((row.get(0)?, row.get(1)?));
+            }
+        }
+
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"UPDATE {table_name} SET key = :key WHERE rowid = :rowid"#,
+            table_name = self.data_table(),
+        ))?;
+        for (rowid, key) in rows.into_iter() {
+            stmt.execute(rusqlite::named_params! {
+                ":key": Key::try_from_legacy_bytes(key.as_slice(), version)?.into_bytes(),
+                ":rowid": rowid,
+            })?;
+        }
+        drop(stmt);
+
+        self.create_primary_tables(conn)
+    }
+}
@@ -1,81 +1,66 @@
-use crate::{Error, Table, TableEvent, TableUpdate};
+use crate::index::{FieldPathIndexDef, FieldPathIndexDefs};
+use crate::{Error, Table, TableEvent};
 use rusqlite::Connection;
+use vdb_value::Value;
 
 impl Table {
     pub fn create_table(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
-        // create primary tables
-        conn.execute_batch(
-            format!(r#"
-                CREATE TABLE IF NOT EXISTS {table_name} (
-                  rowid INTEGER PRIMARY KEY AUTOINCREMENT,
-                  key BLOB,
-                  is_deleted BOOL,
-                  is_latest BOOL,
-                  value BLOB
-                );
-
-                CREATE TABLE IF NOT EXISTS {conf_table} (
-                  key INTEGER PRIMARY KEY,
-                  value BLOB
-                );
-
-                CREATE UNIQUE INDEX IF NOT EXISTS idx_{table_name}_key_latest ON {table_name}(key, is_latest) WHERE is_latest = 1;
-            "#,
-                    table_name = self.data_table(),
-                    conf_table = self.conf_table(),
-            ).as_str()
-        )?;
+        self.create_primary_tables(conn)?;
 
         // create index associated tables
         let mut tables_active = Vec::new();
         for index in self.indexes.iter() {
-            tables_active.extend(index.create_table(conn)?.into_iter());
+            tables_active.extend(index.create_table(conn)?);
         }
 
+        // rebuild tables written in older key format
+        self.migrate_key_format(conn)?;
+
         {
-            // refresh index
-            // todo: performance batch update
+            // truncate indexes whose definition changed, catch up below rebuilds them
             for index in self.indexes.iter() {
-                let synced = index.get_data_version(conn)?;
-                self.scan_to_end(conn, synced.unwrap_or_default(), |key, value, version| {
-                    match value {
-                        None => index.table_update(
-                            conn,
-                            &[TableUpdate::Delete(vec![(key.as_slice(), version)])],
-                        )?,
-                        Some(value) => index.table_update(
-                            conn,
-                            &[TableUpdate::Upsert(vec![(
-                                key.as_slice(),
-                                value.as_slice(),
-                                version,
-                            )])],
-                        )?,
-                    }
-                    Ok(())
-                })?;
+                index.sync_definition_version(conn)?;
             }
         }
 
+        // refresh index
+        self.backfill_indexes(conn)?;
+
         {
             // manage associated tables
-            let prev_tables = dbg!(self.load_associated_tables(conn)?);
+            let prev_tables = self.load_associated_tables(conn)?;
 
-            let tables_to_delete = prev_tables
-                .iter()
+            let tables_unregistered = prev_tables
+                .into_iter()
                 .filter(|t| !tables_active.contains(t))
                 .collect::<Vec<_>>();
 
-            for t in tables_to_delete.iter() {
-                let _ = conn.execute_batch(&format!(
-                    r#"DROP TABLE IF EXISTS {table_name};"#,
-                    table_name = t
-                ));
+            if self.drop_unregistered_indexes {
+                self.drop_associated_tables(conn, &tables_unregistered);
+            } else {
+                // index maybe only temporarily unregistered, keep its tables
+                // tracked until dropped explicitly by `drop_index`
+                for t in tables_unregistered.iter() {
+                    log::warn!("keep table {} of unregistered index", t);
+                }
+                tables_active.extend(tables_unregistered);
             }
 
             self.save_associated_tables(conn, tables_active)?;
         }
 
+        {
+            // persist declarative index definitions, definitions of
+            // unregistered indexes follow their tables
+            let mut defs = self.load_field_path_index_defs(conn)?;
+            defs.retain(|def| {
+                !self.drop_unregistered_indexes
+                    && self.get_index_by_name(def.name.as_str()).is_none()
+            });
+            defs.extend(self.field_path_index_defs.iter().cloned());
+            self.save_field_path_index_defs(conn, defs)?;
+        }
+
         {
             self.observers
                 .iter()
@@ -85,6 +70,67 @@ impl Table {
         Ok(())
     }
 
+    /// Append declarative indexes persisted by create_table, indexes
+    /// already appended are skipped
+    pub fn load_field_path_indexes(&mut self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        self.create_primary_tables(conn)?;
+
+        for def in self.load_field_path_index_defs(conn)? {
+            if self.get_index_by_name(def.name.as_str()).is_none() {
+                self.append_field_path_index(def);
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) fn create_primary_tables(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        conn.execute_batch(
+            format!(r#"
+                CREATE TABLE IF NOT EXISTS {table_name} (
+                  rowid INTEGER PRIMARY KEY AUTOINCREMENT,
+                  key BLOB,
+                  is_deleted BOOL,
+                  is_latest BOOL,
+                  value BLOB,
+                  expire_at INTEGER
+                );
+
+                CREATE TABLE IF NOT EXISTS {conf_table} (
+                  key INTEGER PRIMARY KEY,
+                  value BLOB
+                );
+
+                CREATE UNIQUE INDEX IF NOT EXISTS idx_{table_name}_key_latest ON {table_name}(key, is_latest) WHERE is_latest = 1;
+            "#,
+                    table_name = self.data_table(),
+                    conf_table = self.conf_table(),
+            ).as_str()
+        )?;
+
+        // data table created before expiry introduced
+        let has_expire_at: bool = conn.query_row(
+            &format!(
+                r#"SELECT COUNT(*) > 0 FROM pragma_table_info('{table_name}') WHERE name = 'expire_at'"#,
+                table_name = self.data_table(),
+            ),
+            [],
+            |row| row.get(0),
+        )?;
+        if !has_expire_at {
+            conn.execute_batch(&format!(
+                r#"ALTER TABLE {table_name} ADD COLUMN expire_at INTEGER"#,
+                table_name = self.data_table(),
+            ))?;
+        }
+
+        // keeps purge of expired keys cheap
+        conn.execute_batch(&format!(
+            r#"CREATE INDEX IF NOT EXISTS idx_{table_name}_expire_at ON {table_name}(expire_at) WHERE is_latest = 1 AND expire_at IS NOT NULL"#,
+            table_name = self.data_table(),
+        ))?;
+        Ok(())
+    }
+
     pub(super) fn data_table(&self) -> String {
         format!("{}_$_data", self.table_name)
     }
@@ -93,7 +139,16 @@ impl Table {
         format!("{}_$_conf", self.table_name)
     }
 
-    fn save_associated_tables(
+    pub(super) fn drop_associated_tables(&self, conn: &rusqlite::Connection, tables: &[String]) {
+        for t in tables.iter() {
+            let _ = conn.execute_batch(&format!(
+                r#"DROP TABLE IF EXISTS {table_name};"#,
+                table_name = t
+            ));
+        }
+    }
+
+    pub(super) fn save_associated_tables(
         &self,
         conn: &rusqlite::Connection,
         tables: Vec<String>,
@@ -110,7 +165,7 @@ impl Table {
         Ok(())
     }
 
-    fn load_associated_tables(&self, conn: &Connection) -> Result<Vec<String>, Error> {
+    pub(super) fn load_associated_tables(&self, conn: &Connection) -> Result<Vec<String>, Error> {
         let mut stmt = conn.prepare_cached(&format!(
             r#"SELECT value FROM {config_table} WHERE key = 2"#,
             config_table = self.conf_table(),
@@ -119,8 +174,42 @@ impl Table {
 
         Ok(version
             .unwrap_or_default()
-            .split(",")
+            .split(',')
+            .filter(|s| !s.is_empty())
             .map(|s| s.to_string())
             .collect())
     }
+
+    pub(super) fn save_field_path_index_defs(
+        &self,
+        conn: &rusqlite::Connection,
+        defs: Vec<FieldPathIndexDef>,
+    ) -> Result<(), Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"INSERT OR REPLACE INTO {config_table} (key, value) VALUES ( 3, :defs ) "#,
+            config_table = self.conf_table(),
+        ))?;
+
+        stmt.execute(rusqlite::named_params! {
+            ":defs": FieldPathIndexDefs { defs }.to_vec(),
+        })?;
+
+        Ok(())
+    }
+
+    pub(super) fn load_field_path_index_defs(
+        &self,
+        conn: &Connection,
+    ) -> Result<Vec<FieldPathIndexDef>, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT value FROM {config_table} WHERE key = 3"#,
+            config_table = self.conf_table(),
+        ))?;
+        let defs: Option<Vec<u8>> = no_row_to_none!(stmt.query_row([], |row| row.get(0)))?;
+
+        Ok(match defs {
+            None => vec![],
+            Some(defs) => FieldPathIndexDefs::from_slice(defs.as_slice())?.defs,
+        })
+    }
 }
@@ -1,4 +1,4 @@
-use crate::index::{Extractor, Index, IndexOption};
+use crate::index::{Extractor, FieldPathIndexDef, Index, IndexOption};
 use crate::Error;
 
 pub enum TableUpdate<'a> {
@@ -8,9 +8,9 @@ pub enum TableUpdate<'a> {
 
 #[derive(Debug)]
 pub struct TableItemEvent {
-    key: Vec<u8>,
-    from: Option<(Vec<u8>, i64)>,
-    to: Option<(Vec<u8>, i64)>,
+    pub key: Vec<u8>,
+    pub from: Option<(Vec<u8>, i64)>,
+    pub to: Option<(Vec<u8>, i64)>,
 }
 
 #[derive(Debug)]
@@ -25,7 +25,11 @@ pub type TableObserver = Box<dyn Fn(TableEvent<'_>)>;
 pub struct Table {
     table_name: String,
     indexes: Vec<Index>,
+    field_path_index_defs: Vec<FieldPathIndexDef>,
     observers: Vec<TableObserver>,
+    backfill_option: BackfillOption,
+    drop_unregistered_indexes: bool,
+    key_encoded_pk: Option<bool>,
 }
 
 impl Table {
@@ -34,23 +38,57 @@ impl Table {
         Self {
             table_name: name,
             indexes: vec![],
+            field_path_index_defs: vec![],
             observers: vec![],
+            backfill_option: Default::default(),
+            drop_unregistered_indexes: false,
+            key_encoded_pk: None,
         }
     }
 
     /// Append index defined by Extractor
     pub fn append_index(&mut self, name: &str, extractor: Extractor) {
-        let index = Index::new(
-            name,
-            self.table_name.as_str(),
-            IndexOption {
-                without_rowid: true,
-            },
-            extractor,
-        );
+        self.append_index_with_option(name, IndexOption::default(), extractor);
+    }
+
+    /// Append index defined by Extractor with option, bump `option.version`
+    /// when extractor changed to get the index rebuilt
+    pub fn append_index_with_option(
+        &mut self,
+        name: &str,
+        option: IndexOption,
+        extractor: Extractor,
+    ) {
+        let index = Index::new(name, self.table_name.as_str(), option, extractor);
         self.indexes.push(index);
     }
 
+    /// Append declarative index, its definition is persisted by create_table,
+    /// see `load_field_path_indexes`
+    pub fn append_field_path_index(&mut self, def: FieldPathIndexDef) {
+        self.append_index_with_option(def.name.as_str(), def.option(), def.extractor());
+        self.field_path_index_defs.push(def);
+    }
+
+    /// Set how indexes are backfilled in create_table and backfill_indexes
+    pub fn set_backfill_option(&mut self, option: BackfillOption) {
+        self.backfill_option = option;
+    }
+
+    /// By default, create_table keeps tables of indexes no longer appended,
+    /// set to true to drop them
+    pub fn set_drop_unregistered_indexes(&mut self, drop: bool) {
+        self.drop_unregistered_indexes = drop;
+    }
+
+    /// Declare whether keys are `vdb_key::Key` bytes, key format migration
+    /// re-encodes them if true and leaves them as is if false. Until
+    /// declared, a non-empty table written in an older key format is not
+    /// migrated by create_table. Typed table always sets it to true
+    pub fn set_key_encoded_pk(&mut self, key_encoded: bool) {
+        self.key_encoded_pk = Some(key_encoded);
+    }
+
     /// Append an update observer
     pub fn append_observer(&mut self, observer: TableObserver) {
         self.observers.push(observer);
@@ -101,22 +139,38 @@ impl Table {
 }
 
 mod meta;
-pub use meta::*;
 
 mod update;
 pub use update::*;
 
 mod insert;
-pub use insert::*;
 
 mod get;
-pub use get::*;
 
 mod delete;
-pub use delete::*;
 
 mod index;
 pub use index::*;
 
 mod scan;
-pub use scan::*;
+
+mod backfill;
+pub use backfill::*;
+
+mod verify;
+pub use verify::*;
+
+mod fulltext;
+
+mod geo;
+
+mod vector;
+
+mod aggregate;
+
+mod history;
+
+mod key_format;
+
+mod expiry;
+pub use expiry::*;
@@ -3,33 +3,84 @@ use rusqlite::Connection;
 
 impl Table {
     /// scan to end, useful for index catch up
+    /// value is None for deleted keys
     pub fn scan_to_end(
         &self,
         conn: &Connection,
         from_version: i64,
-        mut f: impl FnMut(Vec<u8>, Option<Vec<u8>>, i64) -> Result<(), Error>,
+        f: impl FnMut(Vec<u8>, Option<Vec<u8>>, i64) -> Result<(), Error>,
     ) -> Result<(), Error> {
+        // negative limit means no limit in sqlite
+        self.scan_from(conn, from_version, -1, f)?;
+        Ok(())
+    }
+
+    /// scan at most `limit` latest items after `from_version`, ordered by version.
+    /// value is None for deleted keys, returns scanned item count
+    pub fn scan_from(
+        &self,
+        conn: &Connection,
+        from_version: i64,
+        limit: i64,
+        f: impl FnMut(Vec<u8>, Option<Vec<u8>>, i64) -> Result<(), Error>,
+    ) -> Result<u64, Error> {
+        self.inner_scan_from(conn, from_version, limit, true, f)
+    }
+
+    /// like `scan_from`, but also includes rows replaced by later versions,
+    /// useful for rebuilding history indexes
+    pub fn scan_history_from(
+        &self,
+        conn: &Connection,
+        from_version: i64,
+        limit: i64,
+        f: impl FnMut(Vec<u8>, Option<Vec<u8>>, i64) -> Result<(), Error>,
+    ) -> Result<u64, Error> {
+        self.inner_scan_from(conn, from_version, limit, false, f)
+    }
+
+    pub(super) fn inner_scan_from(
+        &self,
+        conn: &Connection,
+        from_version: i64,
+        limit: i64,
+        latest_only: bool,
+        mut f: impl FnMut(Vec<u8>, Option<Vec<u8>>, i64) -> Result<(), Error>,
+    ) -> Result<u64, Error> {
         let mut stmt = conn.prepare_cached(&format!(
-            r#"SELECT key, value, rowid FROM {table_name} WHERE rowid > :from_version AND is_latest = 1 ORDER BY rowid"#,
+            r#"SELECT key, CASE WHEN is_deleted = 1 THEN NULL ELSE value END, rowid FROM {table_name} WHERE rowid > :from_version AND is_latest >= :is_latest ORDER BY rowid LIMIT :limit"#,
             table_name = self.data_table()
         ))?;
 
         let rows = no_row_to_none!(stmt.query(rusqlite::named_params! {
             ":from_version": from_version,
+            ":is_latest": latest_only,
+            ":limit": limit,
         }))?;
 
         let mut rows = match rows {
-            None => return Ok(()),
+            None => return Ok(0),
             Some(rows) => rows,
         };
 
+        let mut count = 0u64;
         while let Some(row) = rows.next()? {
             let key: Vec<u8> = row.get(0)?;
             let value: Option<Vec<u8>> = row.get(1)?;
             let v = row.get(2)?;
             f(key, value, v)?;
+            count += 1;
         }
 
-        Ok(())
+        Ok(count)
+    }
+
+    /// Latest data version of the table, 0 for empty table
+    pub fn max_version(&self, conn: &Connection) -> Result<i64, Error> {
+        let mut stmt = conn.prepare_cached(&format!(
+            r#"SELECT IFNULL(MAX(rowid), 0) FROM {table_name}"#,
+            table_name = self.data_table()
+        ))?;
+        Ok(stmt.query_row([], |row| row.get(0))?)
     }
 }
@@ -12,6 +12,7 @@ pub enum UpdateResult {
 
 impl Table {
     /// Update for key
+    #[allow(clippy::type_complexity)]
     pub fn update<'a>(
         &'a self,
         conn: &'a mut rusqlite::Connection,
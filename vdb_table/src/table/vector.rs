@@ -0,0 +1,52 @@
+use crate::index::{Index, IndexOption, Neighbour, VectorExtractor, VectorMetric};
+use crate::{Error, Table};
+use rusqlite::Connection;
+use std::collections::BinaryHeap;
+
+impl Table {
+    /// Append vector index over vector returned by extractor
+    pub fn append_vector_index(
+        &mut self,
+        name: &str,
+        option: IndexOption,
+        extractor: VectorExtractor,
+    ) {
+        let index = Index::new_vector(name, self.table_name.as_str(), option, extractor);
+        self.indexes.push(index);
+    }
+
+    /// Top k nearest primary keys to query vector by brute force scan,
+    /// ordered by distance. Vectors with different dimension are skipped
+    pub fn nearest_vectors(
+        &self,
+        conn: &Connection,
+        index_name: &str,
+        query: &[f64],
+        k: usize,
+        metric: VectorMetric,
+    ) -> Result<Vec<(Vec<u8>, f64)>, Error> {
+        let index = self.index_by_name(index_name)?;
+        index.check_readable(conn)?;
+
+        // max heap, top is the farthest of current k nearest
+        let mut heap = BinaryHeap::<Neighbour>::with_capacity(k + 1);
+        index
+            .vector_entries()?
+            .scan(conn, &self.data_table(), query.len(), |pk, vector| {
+                heap.push(Neighbour {
+                    distance: metric.distance(query, &vector),
+                    pk,
+                });
+                if heap.len() > k {
+                    heap.pop();
+                }
+                Ok(())
+            })?;
+
+        Ok(heap
+            .into_sorted_vec()
+            .into_iter()
+            .map(|n| (n.pk, n.distance))
+            .collect())
+    }
+}
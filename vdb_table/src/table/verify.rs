@@ -0,0 +1,82 @@
+use crate::index::Index;
+use crate::{Error, Table};
+use rusqlite::Connection;
+use std::collections::BTreeSet;
+
+/// Difference between index entries and entries extracted from latest data
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct IndexReport {
+    pub name: String,
+    /// (ik, pk) extracted from data but not in index
+    pub missing: Vec<(Vec<u8>, Vec<u8>)>,
+    /// (ik, pk) in index but not extracted from data
+    pub extra: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+impl IndexReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty()
+    }
+}
+
+impl Table {
+    /// Re-run every extractor over latest data and report index entries
+    /// missing or extra
+    pub fn verify_indexes(&self, conn: &Connection) -> Result<Vec<IndexReport>, Error> {
+        self.indexes
+            .iter()
+            .map(|index| self.verify_index(conn, index))
+            .collect()
+    }
+
+    /// Same as `verify_indexes`, also inserts missing entries, deletes
+    /// extra entries and recomputes aggregate summaries, dropping rounding
+    /// error of sums. Returns reports before repair
+    pub fn repair_indexes(&self, conn: &Connection) -> Result<Vec<IndexReport>, Error> {
+        let reports = self.verify_indexes(conn)?;
+
+        for (index, report) in self.indexes.iter().zip(reports.iter()) {
+            index.rebuild_aggregate(conn)?;
+            if report.is_consistent() {
+                continue;
+            }
+            log::warn!(
+                "repair index {}, missing: {}, extra: {}",
+                report.name,
+                report.missing.len(),
+                report.extra.len()
+            );
+            index.insert_entries(conn, &report.missing)?;
+            index.delete_entries(conn, &report.extra)?;
+        }
+
+        Ok(reports)
+    }
+
+    fn verify_index(&self, conn: &Connection, index: &Index) -> Result<IndexReport, Error> {
+        let mut expected = BTreeSet::<(Vec<u8>, Vec<u8>)>::new();
+        self.scan_to_end(conn, 0, |key, value, _version| {
+            if let Some(value) = value {
+                for ik in index.extract(&key, &value)? {
+                    expected.insert((ik, key.clone()));
+                }
+            }
+            Ok(())
+        })?;
+
+        let mut extra = Vec::new();
+        index.for_each_entry(conn, |ik, pk| {
+            let entry = (ik, pk);
+            if !expected.remove(&entry) {
+                extra.push(entry);
+            }
+            Ok(())
+        })?;
+
+        Ok(IndexReport {
+            name: index.name.clone(),
+            missing: expected.into_iter().collect(),
+            extra,
+        })
+    }
+}
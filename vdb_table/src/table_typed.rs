@@ -1,10 +1,16 @@
-use crate::{Error, Table};
+use crate::index::{
+    Aggregate, FieldPathIndexDef, FullTextQuery, GeoBox, GeoPoint, IndexOption, Tokenizer,
+    VectorMetric,
+};
+use crate::{BackfillOption, Error, IndexInfo, IndexReport, Table};
 use std::marker::PhantomData;
+use std::ops::RangeBounds;
+use std::time::Duration;
 use vdb_key::Key;
 
 /// Typed table, wraps underlying table with type, provide strong typed interface
 /// instead of deal with bytes, now user can deal with type directly
-
+///
 /// TableItem
 pub trait TableItem: vdb_value::Value {
     type PrimaryKey: Into<vdb_key::Key> + TryFrom<vdb_key::Key, Error = vdb_key::Error>;
@@ -19,14 +25,12 @@ pub struct TypedTable<Item: TableItem> {
 
 impl<Item: TableItem> TypedTable<Item> {
     pub fn new(name: &str) -> Self {
-        Self {
-            table: Table::new(name.to_string()),
-            _ph: Default::default(),
-        }
+        Self::new_with_table(Table::new(name.to_string()))
     }
 
     /// create typed table with existing table.
-    pub fn new_with_table(table: Table) -> Self {
+    pub fn new_with_table(mut table: Table) -> Self {
+        table.set_key_encoded_pk(true);
         Self {
             table,
             _ph: Default::default(),
@@ -37,6 +41,46 @@ impl<Item: TableItem> TypedTable<Item> {
         self.table.create_table(conn)
     }
 
+    /// Set how indexes are backfilled, see `Table::set_backfill_option`
+    pub fn set_backfill_option(&mut self, option: BackfillOption) {
+        self.table.set_backfill_option(option)
+    }
+
+    /// Continue backfill of indexes, see `Table::backfill_indexes`
+    pub fn backfill_indexes(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        self.table.backfill_indexes(conn)
+    }
+
+    /// List status of all appended indexes, see `Table::list_indexes`
+    pub fn list_indexes(&self, conn: &rusqlite::Connection) -> Result<Vec<IndexInfo>, Error> {
+        self.table.list_indexes(conn)
+    }
+
+    /// Drop index tables and unregister the index, see `Table::drop_index`
+    pub fn drop_index(&mut self, conn: &rusqlite::Connection, name: &str) -> Result<(), Error> {
+        self.table.drop_index(conn, name)
+    }
+
+    /// Truncate index and backfill it from the first version
+    pub fn rebuild_index(&self, conn: &rusqlite::Connection, name: &str) -> Result<(), Error> {
+        self.table.rebuild_index(conn, name)
+    }
+
+    /// Remove all entries of index, see `Table::truncate_index`
+    pub fn truncate_index(&self, conn: &rusqlite::Connection, name: &str) -> Result<(), Error> {
+        self.table.truncate_index(conn, name)
+    }
+
+    /// Report index entries inconsistent with data, see `Table::verify_indexes`
+    pub fn verify_indexes(&self, conn: &rusqlite::Connection) -> Result<Vec<IndexReport>, Error> {
+        self.table.verify_indexes(conn)
+    }
+
+    /// Fix index entries inconsistent with data, see `Table::repair_indexes`
+    pub fn repair_indexes(&self, conn: &rusqlite::Connection) -> Result<Vec<IndexReport>, Error> {
+        self.table.repair_indexes(conn)
+    }
+
     /// append a code defined index, which accepts pk and item,
     /// and convert to IK
     pub fn append_index<F, IK>(&mut self, name: &str, f: F)
@@ -44,8 +88,19 @@ impl<Item: TableItem> TypedTable<Item> {
         F: Fn(&Item::PrimaryKey, &Item) -> Vec<IK> + Send + Sync + 'static,
         IK: Into<Key>,
     {
-        self.table.append_index(
+        self.append_index_with_option(name, IndexOption::default(), f)
+    }
+
+    /// append a code defined index with option, bump `option.version` when
+    /// f's logic changed, so the index gets rebuilt on create_table
+    pub fn append_index_with_option<F, IK>(&mut self, name: &str, option: IndexOption, f: F)
+    where
+        F: Fn(&Item::PrimaryKey, &Item) -> Vec<IK> + Send + Sync + 'static,
+        IK: Into<Key>,
+    {
+        self.table.append_index_with_option(
             name,
+            option,
             Box::new(move |pk, item| {
                 let item = Item::from_slice(item)?;
                 let pk = Item::PrimaryKey::try_from(Key::load_from_bytes_unchecked(pk.to_vec()))?;
@@ -60,11 +115,278 @@ impl<Item: TableItem> TypedTable<Item> {
         )
     }
 
+    /// append a declarative index persisted in database, see
+    /// `Table::append_field_path_index`
+    pub fn append_field_path_index(&mut self, def: FieldPathIndexDef) {
+        self.table.append_field_path_index(def)
+    }
+
+    /// append a full text index over text returned by f
+    pub fn append_fulltext_index<F>(
+        &mut self,
+        name: &str,
+        option: IndexOption,
+        tokenizer: Tokenizer,
+        f: F,
+    ) where
+        F: Fn(&Item) -> String + Send + Sync + 'static,
+    {
+        self.table.append_fulltext_index(
+            name,
+            option,
+            tokenizer,
+            Box::new(move |_pk, item| {
+                let item = Item::from_slice(item)?;
+                Ok(f(&item))
+            }),
+        )
+    }
+
+    /// search full text index, returns primary keys with score, ordered by score desc
+    pub fn search_fulltext(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        query: &FullTextQuery,
+        count: u32,
+    ) -> Result<Vec<(Item::PrimaryKey, u32)>, Error> {
+        self.table
+            .search_fulltext(conn, name, query, count)?
+            .into_iter()
+            .map(|(pk, score)| {
+                let pk = Item::PrimaryKey::try_from(Key::load_from_bytes_unchecked(pk))?;
+                Ok((pk, score))
+            })
+            .collect()
+    }
+
+    /// append a geospatial index over point returned by f
+    pub fn append_geo_index<F>(&mut self, name: &str, option: IndexOption, f: F)
+    where
+        F: Fn(&Item) -> Option<GeoPoint> + Send + Sync + 'static,
+    {
+        self.table.append_geo_index(
+            name,
+            option,
+            Box::new(move |_pk, item| {
+                let item = Item::from_slice(item)?;
+                Ok(f(&item))
+            }),
+        )
+    }
+
+    /// primary keys with point inside the box
+    pub fn geo_within_box(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        geo_box: &GeoBox,
+    ) -> Result<Vec<Item::PrimaryKey>, Error> {
+        self.table
+            .geo_within_box(conn, name, geo_box)?
+            .into_iter()
+            .map(|pk| {
+                Ok(Item::PrimaryKey::try_from(Key::load_from_bytes_unchecked(
+                    pk,
+                ))?)
+            })
+            .collect()
+    }
+
+    /// primary keys with point within `radius` meters of center, with distance,
+    /// ordered by distance
+    pub fn geo_within_radius(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        center: GeoPoint,
+        radius: f64,
+    ) -> Result<Vec<(Item::PrimaryKey, f64)>, Error> {
+        self.table
+            .geo_within_radius(conn, name, center, radius)?
+            .into_iter()
+            .map(|(pk, distance)| {
+                let pk = Item::PrimaryKey::try_from(Key::load_from_bytes_unchecked(pk))?;
+                Ok((pk, distance))
+            })
+            .collect()
+    }
+
+    /// append a vector index over vector returned by f
+    pub fn append_vector_index<F>(&mut self, name: &str, option: IndexOption, f: F)
+    where
+        F: Fn(&Item) -> Option<Vec<f64>> + Send + Sync + 'static,
+    {
+        self.table.append_vector_index(
+            name,
+            option,
+            Box::new(move |_pk, item| {
+                let item = Item::from_slice(item)?;
+                Ok(f(&item))
+            }),
+        )
+    }
+
+    /// top k nearest primary keys to query vector, with distance
+    pub fn nearest_vectors(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        query: &[f64],
+        k: usize,
+        metric: VectorMetric,
+    ) -> Result<Vec<(Item::PrimaryKey, f64)>, Error> {
+        self.table
+            .nearest_vectors(conn, name, query, k, metric)?
+            .into_iter()
+            .map(|(pk, distance)| {
+                let pk = Item::PrimaryKey::try_from(Key::load_from_bytes_unchecked(pk))?;
+                Ok((pk, distance))
+            })
+            .collect()
+    }
+
+    /// count index entries with ik in range
+    pub fn count_index<'a>(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        range: impl RangeBounds<&'a [u8]>,
+    ) -> Result<i64, Error> {
+        self.table.count_index(conn, name, range)
+    }
+
+    /// count distinct primary keys of index entries with ik in range
+    pub fn count_distinct_pk<'a>(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        range: impl RangeBounds<&'a [u8]>,
+    ) -> Result<i64, Error> {
+        self.table.count_distinct_pk(conn, name, range)
+    }
+
+    /// append an aggregate index, f returns group and value of item, the index
+    /// keeps count, sum, min and max per group
+    pub fn append_aggregate_index<F, G>(&mut self, name: &str, option: IndexOption, f: F)
+    where
+        F: Fn(&Item) -> Option<(G, f64)> + Send + Sync + 'static,
+        G: Into<Key>,
+    {
+        self.table.append_aggregate_index(
+            name,
+            option,
+            Box::new(move |_pk, item| {
+                let item = Item::from_slice(item)?;
+                Ok(f(&item).map(|(group, value)| (group.into(), value)))
+            }),
+        )
+    }
+
+    /// aggregated values of group
+    pub fn aggregate<G: Into<Key>>(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        group: G,
+    ) -> Result<Option<Aggregate>, Error> {
+        self.table
+            .aggregate(conn, name, group.into().into_bytes().as_slice())
+    }
+
+    /// aggregated values of all groups, ordered by group
+    pub fn aggregate_groups(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+    ) -> Result<Vec<(Key, Aggregate)>, Error> {
+        Ok(self
+            .table
+            .aggregate_groups(conn, name)?
+            .into_iter()
+            .map(|(group, aggregate)| (Key::load_from_bytes_unchecked(group), aggregate))
+            .collect())
+    }
+
+    /// primary keys and versions of every version which had index key `ik`,
+    /// index must be appended with `IndexOption::history`
+    pub fn index_history<IK: Into<Key>>(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        ik: IK,
+    ) -> Result<Vec<(Item::PrimaryKey, i64)>, Error> {
+        let ik = ik.into().into_bytes();
+        self.table
+            .index_history(conn, name, ik.as_slice()..=ik.as_slice())?
+            .into_iter()
+            .map(|(_ik, pk, version)| {
+                let pk = Item::PrimaryKey::try_from(Key::load_from_bytes_unchecked(pk))?;
+                Ok((pk, version))
+            })
+            .collect()
+    }
+
+    /// primary keys which had index key `ik` at data `version`
+    pub fn index_as_of<IK: Into<Key>>(
+        &self,
+        conn: &rusqlite::Connection,
+        name: &str,
+        ik: IK,
+        version: i64,
+    ) -> Result<Vec<Item::PrimaryKey>, Error> {
+        let ik = ik.into().into_bytes();
+        self.table
+            .index_as_of(conn, name, ik.as_slice()..=ik.as_slice(), version)?
+            .into_iter()
+            .map(|(_ik, pk)| {
+                Ok(Item::PrimaryKey::try_from(Key::load_from_bytes_unchecked(
+                    pk,
+                ))?)
+            })
+            .collect()
+    }
+
     pub fn insert(&self, conn: &mut rusqlite::Connection, item: &Item) -> Result<i64, Error> {
         self.table
             .insert(conn, item.primary_key().into().into_bytes(), item.to_vec())
     }
 
+    /// insert item which expires at `expire_at` unix millis
+    pub fn insert_with_expiry(
+        &self,
+        conn: &mut rusqlite::Connection,
+        item: &Item,
+        expire_at: i64,
+    ) -> Result<i64, Error> {
+        self.table.insert_with_expiry(
+            conn,
+            item.primary_key().into().into_bytes(),
+            item.to_vec(),
+            expire_at,
+        )
+    }
+
+    /// insert item which expires after `ttl`
+    pub fn insert_with_ttl(
+        &self,
+        conn: &mut rusqlite::Connection,
+        item: &Item,
+        ttl: Duration,
+    ) -> Result<i64, Error> {
+        self.table.insert_with_ttl(
+            conn,
+            item.primary_key().into().into_bytes(),
+            item.to_vec(),
+            ttl,
+        )
+    }
+
+    /// Delete expired items, see `Table::purge_expired`
+    pub fn purge_expired(&self, conn: &mut rusqlite::Connection, limit: u32) -> Result<u64, Error> {
+        self.table.purge_expired(conn, limit)
+    }
+
     pub fn delete(
         &self,
         conn: &mut rusqlite::Connection,
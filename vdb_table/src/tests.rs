@@ -13,41 +13,41 @@ fn test_table_create_and_delete() {
     table.create_table(&conn).unwrap();
 
     let v = table
-        .insert(&mut conn, b"abc".to_vec(), b"def".to_vec())
+        .insert(&mut conn, b"abc"[..].to_vec(), b"def"[..].to_vec())
         .unwrap();
     assert_eq!(
-        table.get(&mut conn, b"abc").unwrap().unwrap(),
-        (b"def".to_vec(), v)
+        table.get(&conn, b"abc").unwrap().unwrap(),
+        (b"def"[..].to_vec(), v)
     );
 
     let v2 = table
-        .insert(&mut conn, b"abc".to_vec(), b"foo".to_vec())
+        .insert(&mut conn, b"abc"[..].to_vec(), b"foo"[..].to_vec())
         .unwrap();
     assert_eq!(
-        table.get(&mut conn, b"abc").unwrap().unwrap(),
-        (b"foo".to_vec(), v2)
+        table.get(&conn, b"abc").unwrap().unwrap(),
+        (b"foo"[..].to_vec(), v2)
     );
 
     // try to delete with prev v, should return error
     assert_eq!(
         table
-            .delete_with_version(&mut conn, b"abc".to_vec(), dbg!(v))
+            .delete_with_version(&mut conn, b"abc"[..].to_vec(), dbg!(v))
             .unwrap(),
         0
     );
     assert_eq!(
-        table.get(&mut conn, b"abc").unwrap().unwrap(),
-        (b"foo".to_vec(), v2)
+        table.get(&conn, b"abc").unwrap().unwrap(),
+        (b"foo"[..].to_vec(), v2)
     );
 
     assert_eq!(
         table
-            .delete_with_version(&mut conn, b"abc".to_vec(), v2)
+            .delete_with_version(&mut conn, b"abc"[..].to_vec(), v2)
             .unwrap(),
         3
     );
 
-    assert!(table.get(&mut conn, b"abc").unwrap().is_none());
+    assert!(table.get(&conn, b"abc").unwrap().is_none());
 }
 
 #[test]
@@ -64,8 +64,8 @@ fn test_update() {
     let new_v = table
         .update(
             &mut conn,
-            b"abc".to_vec(),
-            Box::new(|_prev| Ok(UpdateResult::Update(b"foo".to_vec()))),
+            b"abc"[..].to_vec(),
+            Box::new(|_prev| Ok(UpdateResult::Update(b"foo"[..].to_vec()))),
         )
         .unwrap();
     assert_eq!(new_v.unwrap(), 1);
@@ -73,11 +73,11 @@ fn test_update() {
     let new_v = table
         .update(
             &mut conn,
-            b"abc".to_vec(),
+            b"abc"[..].to_vec(),
             Box::new(|prev| {
                 let (prev_body, prev_version) = prev.unwrap();
                 assert_eq!(prev_version, 1);
-                assert_eq!(prev_body, b"foo".to_vec());
+                assert_eq!(prev_body, b"foo"[..].to_vec());
                 Ok(UpdateResult::NotChange)
             }),
         )
@@ -87,10 +87,10 @@ fn test_update() {
     let new_v = table
         .update(
             &mut conn,
-            b"abc".to_vec(),
+            b"abc"[..].to_vec(),
             Box::new(|prev| {
                 let (_prev_body, _prev_version) = prev.unwrap();
-                Ok(UpdateResult::Update(b"foo new".to_vec()))
+                Ok(UpdateResult::Update(b"foo new"[..].to_vec()))
             }),
         )
         .unwrap();
@@ -99,14 +99,13 @@ fn test_update() {
 
 #[test]
 fn test_index_create() {
-    let mut conn = rusqlite::Connection::open("test_db.sqlite").unwrap();
-    // let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
     let mut table = create_test_table(&conn);
     for i in 0..4 {
         let new_v = table
             .update(
                 &mut conn,
-                b"test_key".to_vec(),
+                b"test_key"[..].to_vec(),
                 Box::new(|prev| {
                     Ok(match prev {
                         None => UpdateResult::Update(
@@ -186,7 +185,7 @@ fn create_test_table(conn: &rusqlite::Connection) -> Table {
             Ok(vec![key.into_bytes()])
         }),
     );
-    table.create_table(&conn).unwrap();
+    table.create_table(conn).unwrap();
     table
 }
 
@@ -234,3 +233,1611 @@ fn test_derive() {
 
     assert_eq!(back_model, model);
 }
+
+#[test]
+fn test_index_rebuild_on_version_change() {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+
+    let table_with_index = |conn: &rusqlite::Connection, version: i64, factor: i64| {
+        let mut table = Table::new("test_table".to_string());
+        table.append_index_with_option(
+            "test_index",
+            index::IndexOption {
+                version,
+                ..Default::default()
+            },
+            Box::new(move |_key: &[u8], val: &[u8]| {
+                let model = TestModel::from_slice(val)?;
+                Ok(vec![Key::from(model.val_1 * factor).into_bytes()])
+            }),
+        );
+        table.create_table(conn).unwrap();
+        table
+    };
+
+    let table = table_with_index(&conn, 0, 100);
+    for i in 0..4i64 {
+        let model = TestModel {
+            val_1: i,
+            val_2: i as f64,
+        };
+        table
+            .insert(&mut conn, Key::from(i).into_bytes(), model.to_vec())
+            .unwrap();
+    }
+    table.delete(&mut conn, &Key::from(3).into_bytes()).unwrap();
+
+    let ik_of = |v: i64| Key::from(v).into_bytes();
+    let keys = table
+        .get_by_index(&conn, "test_index", &ik_of(0), 10)
+        .unwrap();
+    assert_eq!(keys.first().unwrap().0, ik_of(0));
+    assert!(keys.iter().any(|(ik, _)| ik.eq(&ik_of(200))));
+
+    // same version, index not touched
+    let table = table_with_index(&conn, 0, 1000);
+    let keys = table
+        .get_by_index(&conn, "test_index", &ik_of(0), 10)
+        .unwrap();
+    assert!(keys.iter().any(|(ik, _)| ik.eq(&ik_of(200))));
+
+    // version bumped, index rebuilt with new extractor
+    let table = table_with_index(&conn, 1, 1000);
+    let keys = table
+        .get_by_index(&conn, "test_index", &ik_of(0), 10)
+        .unwrap();
+    assert_eq!(
+        keys,
+        (0..3i64)
+            .map(|i| (ik_of(i * 1000), Key::from(i).into_bytes()))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn test_index_backfill_in_chunks() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = Table::new("test_table".to_string());
+    table.create_table(&conn).unwrap();
+    for i in 0..25i64 {
+        let model = TestModel {
+            val_1: i,
+            val_2: i as f64,
+        };
+        table
+            .insert(&mut conn, Key::from(i).into_bytes(), model.to_vec())
+            .unwrap();
+    }
+
+    let progresses = Rc::new(RefCell::new(Vec::new()));
+    let progresses_cloned = progresses.clone();
+    table.set_backfill_option(BackfillOption {
+        chunk_size: 10,
+        max_chunks: Some(1),
+        progress: Some(Box::new(move |progress| {
+            progresses_cloned.borrow_mut().push((
+                progress.synced_version,
+                progress.processed,
+                progress.finished,
+            ));
+        })),
+    });
+    table.append_index(
+        "test_index",
+        Box::new(|_key: &[u8], val: &[u8]| {
+            let model = TestModel::from_slice(val)?;
+            Ok(vec![Key::from(model.val_1).into_bytes()])
+        }),
+    );
+
+    // only first chunk backfilled, index is not readable yet
+    table.create_table(&conn).unwrap();
+    assert_eq!(progresses.borrow().as_slice(), &[(10, 10, false)]);
+    assert!(matches!(
+        table.get_by_index(&conn, "test_index", b"", 100),
+        Err(Error::IndexBuilding(_))
+    ));
+
+    // writes during backfill maintain the index, but not synced version
+    table
+        .insert(
+            &mut conn,
+            Key::from(100).into_bytes(),
+            TestModel {
+                val_1: 100,
+                val_2: 0.,
+            }
+            .to_vec(),
+        )
+        .unwrap();
+
+    // resume from synced version, one chunk each call
+    table.backfill_indexes(&conn).unwrap();
+    table.backfill_indexes(&conn).unwrap();
+    assert_eq!(
+        progresses.borrow().as_slice(),
+        &[(10, 10, false), (20, 10, false), (26, 6, true)]
+    );
+
+    // nothing left to backfill
+    table.backfill_indexes(&conn).unwrap();
+    assert_eq!(progresses.borrow().len(), 3);
+
+    let keys = table.get_by_index(&conn, "test_index", b"", 100).unwrap();
+    assert_eq!(keys.len(), 26);
+}
+
+#[test]
+fn test_index_management() {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let table = create_test_table(&conn);
+    for i in 0..5i64 {
+        let model = TestModel {
+            val_1: i,
+            val_2: i as f64,
+        };
+        table
+            .insert(&mut conn, Key::from(i).into_bytes(), model.to_vec())
+            .unwrap();
+    }
+
+    let infos = table.list_indexes(&conn).unwrap();
+    assert_eq!(
+        infos,
+        vec![IndexInfo {
+            name: "test_index".to_string(),
+            tables: vec![
+                "test_table_idx_test_index_data".to_string(),
+                "test_table_idx_test_index_config".to_string(),
+            ],
+            synced_version: Some(5),
+            lag: 0,
+            entry_count: 5,
+            building: false,
+        }]
+    );
+
+    table.truncate_index(&conn, "test_index").unwrap();
+    let info = table.list_indexes(&conn).unwrap().pop().unwrap();
+    assert_eq!((info.synced_version, info.lag), (None, 5));
+    assert_eq!((info.entry_count, info.building), (0, true));
+    assert!(matches!(
+        table.get_by_index(&conn, "test_index", b"", 100),
+        Err(Error::IndexBuilding(_))
+    ));
+
+    table.rebuild_index(&conn, "test_index").unwrap();
+    let info = table.list_indexes(&conn).unwrap().pop().unwrap();
+    assert_eq!((info.synced_version, info.entry_count), (Some(5), 5));
+    assert!(matches!(
+        table.rebuild_index(&conn, "missing_index"),
+        Err(Error::IndexMissing(_))
+    ));
+
+    let table_exists = |conn: &rusqlite::Connection, name: &str| -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [name],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    };
+
+    // index temporarily unregistered, tables are kept
+    let table = Table::new("test_table".to_string());
+    table.create_table(&conn).unwrap();
+    assert!(table_exists(&conn, "test_table_idx_test_index_data"));
+    let model = TestModel {
+        val_1: 5,
+        val_2: 5.,
+    };
+    table
+        .insert(&mut conn, Key::from(5).into_bytes(), model.to_vec())
+        .unwrap();
+
+    // registered again, index catches up writes happened in between
+    let mut table = create_test_table(&conn);
+    let info = table.list_indexes(&conn).unwrap().pop().unwrap();
+    assert_eq!((info.synced_version, info.entry_count), (Some(6), 6));
+
+    table.drop_index(&conn, "test_index").unwrap();
+    assert!(table.list_indexes(&conn).unwrap().is_empty());
+    assert!(!table_exists(&conn, "test_table_idx_test_index_data"));
+    assert!(!table_exists(&conn, "test_table_idx_test_index_config"));
+}
+
+#[test]
+fn test_field_path_index() {
+    #[derive(Value, Default)]
+    struct Address {
+        #[vdb_value(index = 1)]
+        city: String,
+    }
+
+    #[derive(Value, Default)]
+    struct User {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 3)]
+        address: Address,
+
+        #[vdb_value(index = 4)]
+        nickname: Option<String>,
+    }
+
+    impl TableItem for User {
+        type PrimaryKey = i64;
+
+        fn primary_key(&self) -> Self::PrimaryKey {
+            self.id
+        }
+    }
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = TypedTable::<User>::new("user");
+    let def = |name: &str, paths: Vec<Vec<u8>>| {
+        index::FieldPathIndexDef::new(name, 0, &<User as vdb_value::Schema>::schema(), paths)
+    };
+    // path to a struct can't be a key component
+    assert!(matches!(
+        def("address", vec![vec![3]]),
+        Err(Error::IndexTypeMismatch(_))
+    ));
+    table.append_field_path_index(def("city", vec![vec![3, 1], vec![1]]).unwrap());
+    table.append_field_path_index(def("nickname", vec![vec![4]]).unwrap());
+    table.create_table(&conn).unwrap();
+
+    for (id, city) in [(1, "tokyo"), (2, "paris"), (3, "tokyo")] {
+        let user = User {
+            id,
+            address: Address {
+                city: city.to_string(),
+            },
+            nickname: (id == 2).then(|| "pa".to_string()),
+        };
+        table.insert(&mut conn, &user).unwrap();
+    }
+
+    // another process without the definition in code
+    let mut table = Table::new("user".to_string());
+    table.load_field_path_indexes(&conn).unwrap();
+    table.create_table(&conn).unwrap();
+    table.rebuild_index(&conn, "city").unwrap();
+
+    // strings are keyed as a closure index keys them
+    let city_key = |city: &str, id: i64| {
+        Key::from(&[Component::from(city), Component::from(id)][..]).into_bytes()
+    };
+    let keys = table
+        .get_by_index(&conn, "city", &city_key("tokyo", 0), 10)
+        .unwrap();
+    assert_eq!(
+        keys,
+        vec![
+            (city_key("tokyo", 1), Key::from(1).into_bytes()),
+            (city_key("tokyo", 3), Key::from(3).into_bytes()),
+        ]
+    );
+
+    // maintained on insert
+    table
+        .insert(
+            &mut conn,
+            Key::from(4).into_bytes(),
+            User {
+                id: 4,
+                address: Address {
+                    city: "tokyo".to_string(),
+                },
+                nickname: None,
+            }
+            .to_vec(),
+        )
+        .unwrap();
+    let keys = table
+        .get_by_index(&conn, "city", &city_key("tokyo", 0), 10)
+        .unwrap();
+    assert_eq!(keys.len(), 3);
+
+    // None is not indexed
+    let keys = table.get_by_index(&conn, "nickname", b"", 10).unwrap();
+    assert_eq!(
+        keys,
+        vec![(Key::from("pa").into_bytes(), Key::from(2).into_bytes())]
+    );
+}
+
+#[test]
+fn test_verify_and_repair_indexes() {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = Table::new("test_table".to_string());
+    table.append_index(
+        "test_index",
+        Box::new(|_key: &[u8], val: &[u8]| {
+            let model = TestModel::from_slice(val)?;
+            Ok(vec![Key::from(model.val_1 % 2).into_bytes()])
+        }),
+    );
+    table.create_table(&conn).unwrap();
+
+    let model = |i: i64| {
+        TestModel {
+            val_1: i,
+            val_2: i as f64,
+        }
+        .to_vec()
+    };
+    let pk = |i: i64| Key::from(i).into_bytes();
+    for i in 0..4i64 {
+        table.insert(&mut conn, pk(i), model(i)).unwrap();
+    }
+
+    // pk 0 and 2 share ik, moving pk 0 must keep entry of pk 2
+    table.insert(&mut conn, pk(0), model(1)).unwrap();
+    table.delete(&mut conn, &pk(3)).unwrap();
+    let keys = table.get_by_index(&conn, "test_index", b"", 10).unwrap();
+    assert_eq!(keys, vec![(pk(0), pk(2)), (pk(1), pk(0)), (pk(1), pk(1))]);
+    assert!(table.verify_indexes(&conn).unwrap()[0].is_consistent());
+
+    // corrupt index table
+    conn.execute_batch(
+        r#"
+        DELETE FROM test_table_idx_test_index_data WHERE pk = x'018000000000000002';
+        INSERT INTO test_table_idx_test_index_data (ik, pk) VALUES (x'01', x'02');
+        "#,
+    )
+    .unwrap();
+
+    let expected_report = IndexReport {
+        name: "test_index".to_string(),
+        missing: vec![(pk(0), pk(2))],
+        extra: vec![(vec![1], vec![2])],
+    };
+    assert_eq!(table.verify_indexes(&conn).unwrap(), vec![expected_report]);
+
+    table.repair_indexes(&conn).unwrap();
+    assert!(table.verify_indexes(&conn).unwrap()[0].is_consistent());
+}
+
+#[test]
+fn test_tokenizer() {
+    let tokenizer = index::Tokenizer { stemming: false };
+    assert_eq!(
+        tokenizer.tokenize("Hello, Wörld! foo_bar  42"),
+        vec!["hello", "wörld", "foo", "bar", "42"]
+    );
+
+    let tokenizer = index::Tokenizer { stemming: true };
+    assert_eq!(
+        tokenizer.tokenize("Running runs jumped is cats classes"),
+        vec!["run", "run", "jump", "is", "cat", "class"]
+    );
+}
+
+#[test]
+fn test_fulltext_index() {
+    use index::FullTextQuery::*;
+
+    #[derive(Value, Default)]
+    struct Note {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 2)]
+        text: String,
+    }
+
+    impl TableItem for Note {
+        type PrimaryKey = i64;
+
+        fn primary_key(&self) -> Self::PrimaryKey {
+            self.id
+        }
+    }
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = TypedTable::<Note>::new("note");
+    table.append_fulltext_index(
+        "text",
+        Default::default(),
+        index::Tokenizer { stemming: true },
+        |note| note.text.clone(),
+    );
+    table.create_table(&conn).unwrap();
+
+    for (id, text) in [
+        (1, "Rust database notes"),
+        (2, "Databases and more databases"),
+        (3, "Cooking notes, rusty pans"),
+    ] {
+        let note = Note {
+            id,
+            text: text.to_string(),
+        };
+        table.insert(&mut conn, &note).unwrap();
+    }
+
+    let search = |conn: &rusqlite::Connection, query| {
+        table.search_fulltext(conn, "text", &query, 10).unwrap()
+    };
+
+    assert_eq!(
+        search(&conn, Term("database".to_string())),
+        vec![(2, 2), (1, 1)]
+    );
+    assert_eq!(search(&conn, Term("rust notes".to_string())), vec![(1, 2)]);
+    assert_eq!(
+        search(&conn, Prefix("Rus".to_string())),
+        vec![(1, 1), (3, 1)]
+    );
+    assert_eq!(
+        search(
+            &conn,
+            Or(vec![
+                Term("cooking".to_string()),
+                Term("databases".to_string())
+            ])
+        ),
+        vec![(2, 2), (1, 1), (3, 1)]
+    );
+    assert_eq!(
+        search(
+            &conn,
+            And(vec![
+                Prefix("note".to_string()),
+                Term("database".to_string())
+            ])
+        ),
+        vec![(1, 2)]
+    );
+
+    // maintained on delete
+    table.delete(&mut conn, 2).unwrap();
+    assert_eq!(search(&conn, Term("database".to_string())), vec![(1, 1)]);
+
+    // postings live in the index's own table
+    let postings: i64 = conn
+        .query_row("SELECT COUNT(*) FROM note_idx_text_terms", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(postings, 7);
+
+    // tokenizer config is persisted, reopening without stemming rebuilds
+    let mut table = TypedTable::<Note>::new("note");
+    table.append_fulltext_index(
+        "text",
+        Default::default(),
+        index::Tokenizer { stemming: false },
+        |note| note.text.clone(),
+    );
+    table.create_table(&conn).unwrap();
+    let search = |conn: &rusqlite::Connection, query| {
+        table.search_fulltext(conn, "text", &query, 10).unwrap()
+    };
+    assert_eq!(search(&conn, Term("note".to_string())), vec![]);
+    assert_eq!(
+        search(&conn, Term("notes".to_string())),
+        vec![(1, 1), (3, 1)]
+    );
+}
+
+#[test]
+fn test_geo_index() {
+    use index::{GeoBox, GeoPoint};
+
+    #[derive(Value, Default)]
+    struct Place {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 2)]
+        lat: f64,
+
+        #[vdb_value(index = 3)]
+        lon: f64,
+    }
+
+    impl TableItem for Place {
+        type PrimaryKey = i64;
+
+        fn primary_key(&self) -> Self::PrimaryKey {
+            self.id
+        }
+    }
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = TypedTable::<Place>::new("place");
+    table.append_geo_index("location", Default::default(), |place| {
+        Some(GeoPoint::new(place.lat, place.lon))
+    });
+    table.create_table(&conn).unwrap();
+
+    for (id, lat, lon) in [
+        (1, 35.6762, 139.6503),  // tokyo
+        (2, 35.4437, 139.6380),  // yokohama
+        (3, 34.6937, 135.5023),  // osaka
+        (4, -34.6037, -58.3816), // buenos aires
+        (5, -18.1248, 178.4501), // suva
+        (6, -18.0, -179.9),      // east of antimeridian
+    ] {
+        table.insert(&mut conn, &Place { id, lat, lon }).unwrap();
+    }
+
+    let japan = GeoBox {
+        min_lat: 30.,
+        min_lon: 130.,
+        max_lat: 40.,
+        max_lon: 145.,
+    };
+    assert_eq!(
+        table.geo_within_box(&conn, "location", &japan).unwrap(),
+        vec![1, 2, 3]
+    );
+
+    let south_america = GeoBox {
+        min_lat: -60.,
+        min_lon: -80.,
+        max_lat: 0.,
+        max_lon: -30.,
+    };
+    assert_eq!(
+        table
+            .geo_within_box(&conn, "location", &south_america)
+            .unwrap(),
+        vec![4]
+    );
+
+    let across_antimeridian = GeoBox {
+        min_lat: -19.,
+        min_lon: 178.,
+        max_lat: -17.,
+        max_lon: -179.,
+    };
+    assert_eq!(
+        table
+            .geo_within_box(&conn, "location", &across_antimeridian)
+            .unwrap(),
+        vec![5, 6]
+    );
+
+    let tokyo = GeoPoint::new(35.6762, 139.6503);
+    let nearby = table
+        .geo_within_radius(&conn, "location", tokyo, 50_000.)
+        .unwrap();
+    assert_eq!(nearby.iter().map(|x| x.0).collect::<Vec<_>>(), vec![1, 2]);
+    assert!((nearby[1].1 - 26_000.).abs() < 1_000.);
+
+    let suva = GeoPoint::new(-18.1248, 178.4501);
+    let nearby = table
+        .geo_within_radius(&conn, "location", suva, 200_000.)
+        .unwrap();
+    assert_eq!(nearby.iter().map(|x| x.0).collect::<Vec<_>>(), vec![5, 6]);
+
+    // maintained on delete
+    table.delete(&mut conn, 2).unwrap();
+    assert_eq!(
+        table.geo_within_box(&conn, "location", &japan).unwrap(),
+        vec![1, 3]
+    );
+
+    // circle close to but not covering the north pole spans all longitudes
+    for (id, lat, lon) in [(7, 89.7, 55.), (8, 89.5, -20.), (9, 89.5, 130.)] {
+        table.insert(&mut conn, &Place { id, lat, lon }).unwrap();
+    }
+    let near_pole = GeoPoint::new(89.5, 10.);
+    let nearby = table
+        .geo_within_radius(&conn, "location", near_pole, 50_000.)
+        .unwrap();
+    assert_eq!(nearby.iter().map(|x| x.0).collect::<Vec<_>>(), vec![8, 7]);
+}
+
+#[test]
+fn test_vector_index() {
+    use index::VectorMetric;
+
+    #[derive(Value, Default)]
+    struct Doc {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 2)]
+        embedding: Vec<f64>,
+    }
+
+    impl TableItem for Doc {
+        type PrimaryKey = i64;
+
+        fn primary_key(&self) -> Self::PrimaryKey {
+            self.id
+        }
+    }
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = TypedTable::<Doc>::new("doc");
+    table.append_vector_index("embedding", Default::default(), |doc| {
+        Some(doc.embedding.clone())
+    });
+    table.create_table(&conn).unwrap();
+
+    for (id, embedding) in [
+        (1, vec![1., 0.]),
+        (2, vec![0., 1.]),
+        (3, vec![2., 2.]),
+        (4, vec![-1., 0.1]),
+        (5, vec![1., 0., 0.]),
+    ] {
+        table.insert(&mut conn, &Doc { id, embedding }).unwrap();
+    }
+
+    let nearest = |conn: &rusqlite::Connection, query: &[f64], k, metric| {
+        table
+            .nearest_vectors(conn, "embedding", query, k, metric)
+            .unwrap()
+            .into_iter()
+            .map(|(pk, _)| pk)
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(nearest(&conn, &[1., 0.2], 2, VectorMetric::L2), vec![1, 2]);
+    assert_eq!(
+        nearest(&conn, &[10., 10.], 3, VectorMetric::Cosine),
+        vec![3, 1, 2]
+    );
+
+    let result = table
+        .nearest_vectors(&conn, "embedding", &[0., 0.], 1, VectorMetric::L2)
+        .unwrap();
+    assert_eq!(result, vec![(1, 1.)]);
+
+    // vectors are kept by pk, index key only holds the dimension
+    let ik_length: i64 = conn
+        .query_row(
+            "SELECT MAX(length(ik)) FROM doc_idx_embedding_data",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert!(ik_length < 16);
+    let vector_count: i64 = conn
+        .query_row("SELECT COUNT(*) FROM doc_idx_embedding_vec", [], |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(vector_count, 5);
+
+    // maintained on update and delete
+    table
+        .insert(
+            &mut conn,
+            &Doc {
+                id: 1,
+                embedding: vec![5., 5.],
+            },
+        )
+        .unwrap();
+    table.delete(&mut conn, 2).unwrap();
+    assert_eq!(nearest(&conn, &[1., 0.2], 2, VectorMetric::L2), vec![4, 3]);
+}
+
+#[test]
+fn test_count_and_aggregate_index() {
+    use index::Aggregate;
+
+    #[derive(Value, Default, Clone)]
+    struct Ticket {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 2)]
+        tenant: i64,
+
+        #[vdb_value(index = 3)]
+        open: i64,
+
+        #[vdb_value(index = 4)]
+        cost: f64,
+    }
+
+    impl TableItem for Ticket {
+        type PrimaryKey = i64;
+
+        fn primary_key(&self) -> Self::PrimaryKey {
+            self.id
+        }
+    }
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = TypedTable::<Ticket>::new("ticket");
+    table.append_index("tenant", |_pk, ticket: &Ticket| vec![ticket.tenant]);
+    table.append_aggregate_index("open_per_tenant", Default::default(), |ticket| {
+        (ticket.open == 1).then_some((ticket.tenant, ticket.cost))
+    });
+    table.create_table(&conn).unwrap();
+
+    let tickets = [(1, 1, 1, 3.), (2, 1, 1, 5.), (3, 1, 0, 7.), (4, 2, 1, 1.)].map(
+        |(id, tenant, open, cost)| Ticket {
+            id,
+            tenant,
+            open,
+            cost,
+        },
+    );
+    for ticket in tickets.iter() {
+        table.insert(&mut conn, ticket).unwrap();
+    }
+
+    let aggregate = |conn: &rusqlite::Connection, tenant: i64| {
+        table.aggregate(conn, "open_per_tenant", tenant).unwrap()
+    };
+    assert_eq!(
+        aggregate(&conn, 1),
+        Some(Aggregate {
+            count: 2,
+            sum: 8.,
+            min: 3.,
+            max: 5.,
+        })
+    );
+    assert_eq!(
+        table
+            .aggregate_groups(&conn, "open_per_tenant")
+            .unwrap()
+            .into_iter()
+            .map(|(tenant, aggregate)| (i64::try_from(tenant).unwrap(), aggregate.count))
+            .collect::<Vec<_>>(),
+        vec![(1, 2), (2, 1)]
+    );
+
+    // close the cheapest ticket, min is recomputed
+    let mut closed = tickets[0].clone();
+    closed.open = 0;
+    table.insert(&mut conn, &closed).unwrap();
+    assert_eq!(
+        aggregate(&conn, 1),
+        Some(Aggregate {
+            count: 1,
+            sum: 5.,
+            min: 5.,
+            max: 5.,
+        })
+    );
+
+    // last open ticket of tenant deleted
+    table.delete(&mut conn, 4).unwrap();
+    assert_eq!(aggregate(&conn, 2), None);
+
+    // counts over plain index
+    let tenant_1 = Key::from(1).into_bytes();
+    let tenant_2 = Key::from(2).into_bytes();
+    assert_eq!(table.count_index(&conn, "tenant", ..).unwrap(), 3);
+    assert_eq!(
+        table
+            .count_index(&conn, "tenant", tenant_1.as_slice()..tenant_2.as_slice())
+            .unwrap(),
+        3
+    );
+    assert_eq!(
+        table
+            .count_distinct_pk(&conn, "tenant", tenant_2.as_slice()..)
+            .unwrap(),
+        0
+    );
+    assert!(matches!(
+        table.aggregate(&conn, "tenant", 1),
+        Err(Error::IndexTypeMismatch(_))
+    ));
+
+    // drifted sum is recomputed by repair
+    conn.execute(
+        "UPDATE ticket_idx_open_per_tenant_agg SET sum = sum + 0.001",
+        [],
+    )
+    .unwrap();
+    table.repair_indexes(&conn).unwrap();
+    assert_eq!(aggregate(&conn, 1).unwrap().sum, 5.);
+
+    // dropping the index also drops its summary table
+    let agg_tables = |conn: &rusqlite::Connection| -> i64 {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'ticket_idx_open_per_tenant_agg'",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap()
+    };
+    assert_eq!(agg_tables(&conn), 1);
+    table.drop_index(&conn, "open_per_tenant").unwrap();
+    assert_eq!(agg_tables(&conn), 0);
+}
+
+#[test]
+fn test_history_index() {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+
+    let mut table = Table::new("test_table".to_string());
+    table.append_index_with_option(
+        "status",
+        index::IndexOption {
+            history: true,
+            ..Default::default()
+        },
+        Box::new(|_key: &[u8], val: &[u8]| {
+            let model = TestModel::from_slice(val)?;
+            Ok(vec![Key::from(model.val_1).into_bytes()])
+        }),
+    );
+    table.create_table(&conn).unwrap();
+
+    let pk_of = |v: i64| Key::from(v).into_bytes();
+    let mut insert = |pk: i64, status: i64| {
+        let model = TestModel {
+            val_1: status,
+            val_2: 0.,
+        };
+        table.insert(&mut conn, pk_of(pk), model.to_vec()).unwrap()
+    };
+    let v1 = insert(1, 1);
+    let v2 = insert(2, 1);
+    let v3 = insert(1, 2);
+    let v4 = table.delete(&mut conn, &pk_of(2)).unwrap();
+
+    let status_1 = Key::from(1).into_bytes();
+    let status_2 = Key::from(2).into_bytes();
+    let history = |table: &Table, conn: &rusqlite::Connection| {
+        table.index_history(conn, "status", ..).unwrap()
+    };
+    assert_eq!(
+        history(&table, &conn),
+        vec![
+            (status_1.clone(), pk_of(1), v1),
+            (status_1.clone(), pk_of(2), v2),
+            (status_2.clone(), pk_of(1), v3),
+        ]
+    );
+
+    let as_of = |status: &[u8], version: i64| {
+        table
+            .index_as_of(&conn, "status", status..=status, version)
+            .unwrap()
+            .into_iter()
+            .map(|(_ik, pk)| pk)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(as_of(&status_1, v1), vec![pk_of(1)]);
+    assert_eq!(as_of(&status_1, v2), vec![pk_of(1), pk_of(2)]);
+    assert_eq!(as_of(&status_1, v3), vec![pk_of(2)]);
+    assert!(as_of(&status_1, v4).is_empty());
+    assert_eq!(as_of(&status_2, v4), vec![pk_of(1)]);
+
+    // latest entries are still maintained as a normal index
+    assert_eq!(table.count_index(&conn, "status", ..).unwrap(), 1);
+
+    // rebuild replays full history of data table
+    let before = history(&table, &conn);
+    table.rebuild_index(&conn, "status").unwrap();
+    assert_eq!(history(&table, &conn), before);
+    assert_eq!(as_of(&status_1, v2), vec![pk_of(1), pk_of(2)]);
+
+    // history turned on for an existing index without version bump,
+    // earlier versions are backfilled
+    let mut table = Table::new("test_table".to_string());
+    let extractor = |_key: &[u8], val: &[u8]| {
+        let model = TestModel::from_slice(val)?;
+        Ok(vec![Key::from(model.val_1).into_bytes()])
+    };
+    table.append_index("plain", Box::new(extractor));
+    table.create_table(&conn).unwrap();
+
+    let mut table = Table::new("test_table".to_string());
+    table.append_index_with_option(
+        "plain",
+        index::IndexOption {
+            history: true,
+            ..Default::default()
+        },
+        Box::new(extractor),
+    );
+    table.create_table(&conn).unwrap();
+    assert_eq!(
+        table.index_history(&conn, "plain", ..).unwrap(),
+        vec![
+            (status_1.clone(), pk_of(1), v1),
+            (status_1, pk_of(2), v2),
+            (status_2, pk_of(1), v3),
+        ]
+    );
+}
+
+#[test]
+fn test_expiry() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+
+    let purged = Rc::new(RefCell::new(Vec::new()));
+    let mut table = Table::new("test_table".to_string());
+    let extractor = |_key: &[u8], val: &[u8]| {
+        let model = TestModel::from_slice(val)?;
+        Ok(vec![Key::from(model.val_1).into_bytes()])
+    };
+    table.append_index("test_index", Box::new(extractor));
+    table.append_index_with_option(
+        "history_index",
+        index::IndexOption {
+            history: true,
+            ..Default::default()
+        },
+        Box::new(extractor),
+    );
+    table.append_aggregate_index(
+        "aggregate_index",
+        Default::default(),
+        Box::new(|_key: &[u8], val: &[u8]| {
+            let model = TestModel::from_slice(val)?;
+            Ok(Some((Key::from(model.val_1), model.val_2)))
+        }),
+    );
+    {
+        let purged = purged.clone();
+        table.append_observer(Box::new(move |event: TableEvent<'_>| {
+            if let TableEvent::DataUpdates(events) = event {
+                for e in events.iter().filter(|e| e.to.is_none()) {
+                    purged.borrow_mut().push(e.key.clone());
+                }
+            }
+        }));
+    }
+    table.create_table(&conn).unwrap();
+
+    let pk_of = |v: i64| Key::from(v).into_bytes();
+    let model = TestModel {
+        val_1: 7,
+        val_2: 0.,
+    };
+    let expire_at = now_millis() - 1000;
+    table
+        .insert_with_expiry(&mut conn, pk_of(1), model.to_vec(), expire_at)
+        .unwrap();
+    table
+        .insert_with_ttl(
+            &mut conn,
+            pk_of(2),
+            model.to_vec(),
+            Duration::from_secs(3600),
+        )
+        .unwrap();
+    table.insert(&mut conn, pk_of(3), model.to_vec()).unwrap();
+
+    // expired key reads as absent
+    assert!(table.get(&conn, &pk_of(1)).unwrap().is_none());
+    assert!(table.get(&conn, &pk_of(2)).unwrap().is_some());
+    assert_eq!(table.get_expiry(&conn, &pk_of(1)).unwrap(), Some(expire_at));
+    assert_eq!(table.get_expiry(&conn, &pk_of(3)).unwrap(), None);
+    let ik = Key::from(7).into_bytes();
+    let pks = |table: &Table, conn: &rusqlite::Connection| {
+        table
+            .get_by_index(conn, "test_index", &ik, 10)
+            .unwrap()
+            .into_iter()
+            .map(|(_ik, pk)| pk)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(pks(&table, &conn), vec![pk_of(2), pk_of(3)]);
+    // expired entry ahead of the live ones does not use up count
+    assert_eq!(
+        table
+            .get_by_index(&conn, "test_index", &ik, 1)
+            .unwrap()
+            .into_iter()
+            .map(|(_ik, pk)| pk)
+            .collect::<Vec<_>>(),
+        vec![pk_of(2)]
+    );
+    assert_eq!(table.count_index(&conn, "test_index", ..).unwrap(), 2);
+    assert_eq!(table.count_distinct_pk(&conn, "test_index", ..).unwrap(), 2);
+    assert_eq!(
+        table
+            .aggregate(&conn, "aggregate_index", &ik)
+            .unwrap()
+            .map(|aggregate| aggregate.count),
+        Some(2)
+    );
+    assert_eq!(
+        table
+            .aggregate_groups(&conn, "aggregate_index")
+            .unwrap()
+            .into_iter()
+            .map(|(group, aggregate)| (group, aggregate.count))
+            .collect::<Vec<_>>(),
+        vec![(ik.clone(), 2)]
+    );
+    let history_pks = table
+        .index_history(&conn, "history_index", ..)
+        .unwrap()
+        .into_iter()
+        .map(|(_ik, pk, _version)| pk)
+        .collect::<Vec<_>>();
+    assert_eq!(history_pks, vec![pk_of(2), pk_of(3)]);
+    let version = table.max_version(&conn).unwrap();
+    assert_eq!(
+        table
+            .index_as_of(&conn, "history_index", .., version)
+            .unwrap()
+            .len(),
+        2
+    );
+
+    // purge writes tombstone, index and observers see a delete
+    assert_eq!(table.purge_expired(&mut conn, 10).unwrap(), 1);
+    assert_eq!(purged.borrow().clone(), vec![pk_of(1)]);
+    assert_eq!(table.count_index(&conn, "test_index", ..).unwrap(), 2);
+    assert_eq!(table.purge_expired(&mut conn, 10).unwrap(), 0);
+
+    // key can be inserted again after expiry
+    table.insert(&mut conn, pk_of(1), model.to_vec()).unwrap();
+    assert!(table.get(&conn, &pk_of(1)).unwrap().is_some());
+}
+
+#[test]
+fn test_key_format_migration() {
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+
+    let typed_table = |conn: &rusqlite::Connection, with_val_1: bool| {
+        let mut table = TypedTable::<TestModel>::new("test_table");
+        table.append_index("by_val_2", |_pk, model: &TestModel| {
+            vec![Component::from(model.val_2)]
+        });
+        if with_val_1 {
+            table.append_index("by_val_1", |_pk, model: &TestModel| {
+                vec![Component::from(model.val_1)]
+            });
+        }
+        table.create_table(conn).unwrap();
+        table
+    };
+
+    let table = typed_table(&conn, true);
+    for i in [-2i64, -1, 1, 2] {
+        let model = TestModel {
+            val_1: i,
+            val_2: i as f64,
+        };
+        table.insert(&mut conn, &model).unwrap();
+    }
+
+    // negative values sort before positive ones in index range scan
+    let range = |l: f64, h: f64| {
+        (
+            Key::from(Component::from(l)).into_bytes(),
+            Key::from(Component::from(h)).into_bytes(),
+        )
+    };
+    let (lower, higher) = range(-1.5, 1.5);
+    assert_eq!(
+        table
+            .count_index(&conn, "by_val_2", lower.as_slice()..higher.as_slice())
+            .unwrap(),
+        2
+    );
+
+    // rewrite table as written by key format 0
+    for i in [-2i64, -1, 1, 2] {
+        let mut legacy = vec![1u8];
+        legacy.extend_from_slice(&i.to_be_bytes());
+        conn.execute(
+            "UPDATE test_table_$_data SET key = ? WHERE key = ?",
+            rusqlite::params![legacy, Key::from(i).into_bytes()],
+        )
+        .unwrap();
+    }
+    conn.execute("DELETE FROM test_table_$_conf WHERE key = 4", [])
+        .unwrap();
+    assert!(table.get(&conn, -2).unwrap().is_none());
+
+    // create_table migrates keys and rebuilds indexes, tables of the
+    // unregistered by_val_1 are kept but reset
+    let table = typed_table(&conn, false);
+    let (model, _) = table.get(&conn, -2).unwrap().unwrap();
+    assert_eq!(model.val_2, -2.);
+    assert_eq!(
+        table
+            .count_index(&conn, "by_val_2", lower.as_slice()..higher.as_slice())
+            .unwrap(),
+        2
+    );
+    let version: i64 = conn
+        .query_row(
+            "SELECT value FROM test_table_$_conf WHERE key = 4",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(version, vdb_key::KEY_FORMAT_VERSION);
+    let stale: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM test_table_idx_by_val_1_data",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(stale, 0);
+
+    // appended again, by_val_1 is rebuilt with migrated keys
+    let table = typed_table(&conn, true);
+    let (lower, higher) = (Key::from(-1).into_bytes(), Key::from(2).into_bytes());
+    assert_eq!(
+        table
+            .count_index(&conn, "by_val_1", lower.as_slice()..higher.as_slice())
+            .unwrap(),
+        2
+    );
+
+    // plain table is not migrated until its pk encoding is declared
+    let mut plain = Table::new("plain_table".to_string());
+    plain.create_table(&conn).unwrap();
+    plain
+        .insert(&mut conn, b"key"[..].to_vec(), b"value"[..].to_vec())
+        .unwrap();
+    conn.execute("DELETE FROM plain_table_$_conf WHERE key = 4", [])
+        .unwrap();
+    plain.create_table(&conn).unwrap();
+    assert_eq!(plain.key_format_version(&conn).unwrap(), 0);
+
+    plain.set_key_encoded_pk(false);
+    plain.create_table(&conn).unwrap();
+    assert_eq!(
+        plain.key_format_version(&conn).unwrap(),
+        vdb_key::KEY_FORMAT_VERSION
+    );
+    assert!(plain.get(&conn, b"key").unwrap().is_some());
+}
+
+#[test]
+fn test_composite_primary_key() {
+    #[derive(Value, Default, Debug, PartialEq)]
+    struct Order {
+        #[vdb_value(index = 1)]
+        tenant: String,
+
+        #[vdb_value(index = 2)]
+        id: i64,
+
+        #[vdb_value(index = 3)]
+        status: String,
+    }
+
+    impl TableItem for Order {
+        type PrimaryKey = (String, i64);
+
+        fn primary_key(&self) -> Self::PrimaryKey {
+            (self.tenant.clone(), self.id)
+        }
+    }
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = TypedTable::<Order>::new("order");
+    table.append_index("status", |(tenant, _id), order: &Order| {
+        vec![(tenant.clone(), order.status.clone())]
+    });
+    table.create_table(&conn).unwrap();
+
+    for (tenant, id, status) in [("a", 1, "open"), ("a", 2, "done"), ("b", 1, "open")] {
+        let order = Order {
+            tenant: tenant.to_string(),
+            id,
+            status: status.to_string(),
+        };
+        table.insert(&mut conn, &order).unwrap();
+    }
+
+    let (order, _) = table.get(&conn, ("b".to_string(), 1)).unwrap().unwrap();
+    assert_eq!(order.status, "open");
+    assert_eq!(
+        table
+            .count_index(&conn, "status", Key::from(("a", "open")).as_bytes()..)
+            .unwrap(),
+        2
+    );
+
+    table.delete(&mut conn, ("a".to_string(), 1)).unwrap();
+    assert!(table.get(&conn, ("a".to_string(), 1)).unwrap().is_none());
+    assert!(table.get(&conn, ("a".to_string(), 2)).unwrap().is_some());
+}
+
+#[test]
+fn test_derive_key() {
+    #[derive(Key, Debug, Clone, PartialEq)]
+    struct EventKey {
+        tenant: String,
+        #[vdb_key(desc)]
+        at: i64,
+    }
+
+    #[derive(Key, Debug, PartialEq)]
+    struct KindKey(String, i64);
+
+    #[derive(Value, Default)]
+    struct Event {
+        #[vdb_value(index = 1)]
+        tenant: String,
+
+        #[vdb_value(index = 2)]
+        at: i64,
+
+        #[vdb_value(index = 3)]
+        kind: String,
+    }
+
+    impl TableItem for Event {
+        type PrimaryKey = EventKey;
+
+        fn primary_key(&self) -> Self::PrimaryKey {
+            EventKey {
+                tenant: self.tenant.clone(),
+                at: self.at,
+            }
+        }
+    }
+
+    let key = Key::from(EventKey {
+        tenant: "a".to_string(),
+        at: 1,
+    });
+    assert_eq!(
+        key.as_components(),
+        vec![Component::from("a"), Component::from(vdb_key::Desc(1i64))]
+    );
+    assert_eq!(
+        EventKey::try_from(key).unwrap(),
+        EventKey {
+            tenant: "a".to_string(),
+            at: 1,
+        }
+    );
+    assert_eq!(
+        KindKey::try_from(Key::from(("a", "b"))),
+        Err(vdb_key::Error::ComponentMismatch {
+            index: 1,
+            found: vdb_key::Ty::Str,
+        })
+    );
+
+    let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut table = TypedTable::<Event>::new("event");
+    table.append_index("kind", |pk: &EventKey, event: &Event| {
+        vec![KindKey(event.kind.clone(), pk.at)]
+    });
+    table.create_table(&conn).unwrap();
+
+    for (at, kind) in [(1, "login"), (2, "logout"), (3, "login")] {
+        let event = Event {
+            tenant: "a".to_string(),
+            at,
+            kind: kind.to_string(),
+        };
+        table.insert(&mut conn, &event).unwrap();
+    }
+
+    // descending field, latest event first
+    let first: Vec<u8> = conn
+        .query_row(
+            "SELECT key FROM event_$_data WHERE is_latest = 1 ORDER BY key LIMIT 1",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    let first = EventKey::try_from(Key::load_from_bytes_unchecked(first)).unwrap();
+    assert_eq!(first.at, 3);
+    let (lower, higher) = Key::from(Component::from("login")).prefix_range();
+    assert_eq!(
+        table
+            .count_index(&conn, "kind", lower.as_slice()..higher.as_slice())
+            .unwrap(),
+        2
+    );
+}
+
+#[test]
+fn test_derived_schema() {
+    use vdb_value::{DynamicStruct, Schema, TypeSchema};
+
+    #[derive(Value, Default)]
+    struct Address {
+        #[vdb_value(index = 1)]
+        city: String,
+    }
+
+    #[derive(Value, Default)]
+    struct User {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 2)]
+        scores: Vec<f64>,
+
+        #[vdb_value(index = 3)]
+        address: Address,
+    }
+
+    let schema = match User::schema() {
+        TypeSchema::Struct(schema) => schema,
+        schema => panic!("{:?}", schema),
+    };
+    assert_eq!(schema.name, "User");
+    assert_eq!(
+        schema
+            .fields
+            .iter()
+            .map(|field| (field.name.as_str(), field.index, field.schema.ty()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("id", 1, vdb_value::Ty::I64),
+            ("scores", 2, vdb_value::Ty::List),
+            ("address", 3, vdb_value::Ty::Struct),
+        ]
+    );
+    assert_eq!(
+        schema.field_by_name("scores").unwrap().schema,
+        TypeSchema::List(Box::new(TypeSchema::F64))
+    );
+
+    // decode a stored blob with field names
+    let user = User {
+        id: 7,
+        scores: vec![],
+        address: Address {
+            city: "tokyo".to_string(),
+        },
+    };
+    let st = DynamicStruct::from_slice(&user.to_vec()).unwrap();
+    let address = schema.field_by_name("address").unwrap();
+    let city = match &address.schema {
+        TypeSchema::Struct(address_schema) => address_schema.field_by_name("city").unwrap(),
+        schema => panic!("{:?}", schema),
+    };
+    assert!(matches!(
+        st.get_path(&[address.index, city.index]),
+        Some(vdb_value::DynamicValue::Bytes(city)) if city.as_slice() == b"tokyo"
+    ));
+}
+
+#[test]
+fn test_derive_enum() {
+    use vdb_value::{Schema, TypeSchema};
+
+    #[derive(Value, Default, Debug, PartialEq)]
+    enum Shape {
+        #[default]
+        #[vdb_value(index = 1)]
+        Empty,
+
+        #[vdb_value(index = 2)]
+        Circle(f64),
+
+        #[vdb_value(index = 3)]
+        Rect {
+            #[vdb_value(index = 1)]
+            w: f64,
+
+            #[vdb_value(index = 2)]
+            h: f64,
+        },
+    }
+
+    // an older version, without Rect
+    #[derive(Value, Default, Debug, PartialEq)]
+    enum ShapeV1 {
+        #[default]
+        #[vdb_value(index = 1)]
+        Empty,
+
+        #[vdb_value(index = 2)]
+        Circle(f64),
+
+        #[vdb_value(other)]
+        Unknown,
+    }
+
+    #[derive(Value, Default, Debug, PartialEq)]
+    struct Drawing {
+        #[vdb_value(index = 1)]
+        main: Shape,
+
+        #[vdb_value(index = 2)]
+        shapes: Vec<Shape>,
+    }
+
+    let drawing = Drawing {
+        main: Shape::Circle(1.5),
+        shapes: vec![
+            Shape::Rect { w: 2., h: 3. },
+            Shape::Empty,
+            Shape::Circle(-1.),
+        ],
+    };
+    assert_eq!(Drawing::from_slice(&drawing.to_vec()).unwrap(), drawing);
+
+    let rect = Shape::Rect { w: 2., h: 3. }.to_vec();
+    assert_eq!(ShapeV1::from_slice(&rect).unwrap(), ShapeV1::Unknown);
+    assert_eq!(
+        ShapeV1::from_slice(&ShapeV1::Unknown.to_vec()).unwrap(),
+        ShapeV1::Unknown
+    );
+    assert_eq!(
+        Shape::from_slice(&ShapeV1::Circle(1.).to_vec()).unwrap(),
+        Shape::Circle(1.)
+    );
+    assert!(matches!(
+        Shape::from_slice(&ShapeV1::Unknown.to_vec()),
+        Ok(Shape::Empty)
+    ));
+
+    let mut unknown = vdb_value::DynamicStruct::default();
+    unknown.insert(9, vdb_value::DynamicValue::I64(1));
+    assert!(matches!(
+        Shape::from_slice(&unknown.to_vec()),
+        Err(vdb_value::Error::DecodeUnknownVariant(9))
+    ));
+
+    // variants are fields of the enum schema
+    let changes = vdb_value::schema::check_compatible(&Shape::schema(), &ShapeV1::schema());
+    assert!(changes.iter().all(|change| !change.is_breaking()));
+    match Shape::schema() {
+        TypeSchema::Struct(schema) => assert_eq!(
+            schema.field(3).unwrap().schema,
+            TypeSchema::Struct(vdb_value::StructSchema {
+                name: "Rect".to_string(),
+                fields: vec![
+                    vdb_value::FieldSchema {
+                        name: "w".to_string(),
+                        index: 1,
+                        schema: TypeSchema::F64,
+                    },
+                    vdb_value::FieldSchema {
+                        name: "h".to_string(),
+                        index: 2,
+                        schema: TypeSchema::F64,
+                    },
+                ],
+            })
+        ),
+        schema => panic!("{:?}", schema),
+    }
+}
+
+#[test]
+fn test_option_absence() {
+    use std::collections::BTreeMap;
+    use vdb_value::{DynamicStruct, Schema, TypeSchema};
+
+    #[derive(Value, Default, Debug, PartialEq)]
+    struct Inner {
+        #[vdb_value(index = 1)]
+        note: Option<String>,
+    }
+
+    #[derive(Value, Default, Debug, PartialEq)]
+    struct Record {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 2)]
+        score: Option<i64>,
+
+        #[vdb_value(index = 3)]
+        inner: Option<Inner>,
+
+        #[vdb_value(index = 4)]
+        tags: Vec<Option<String>>,
+
+        #[vdb_value(index = 5)]
+        matrix: Vec<Option<Vec<Option<i64>>>>,
+
+        #[vdb_value(index = 6)]
+        attrs: BTreeMap<String, Option<f64>>,
+
+        #[vdb_value(index = 7)]
+        patch: Option<Option<i64>>,
+
+        #[vdb_value(index = 8)]
+        patches: Vec<Option<Option<String>>>,
+    }
+
+    // written before, with defaults stored
+    #[derive(Value, Default)]
+    struct RecordV1 {
+        #[vdb_value(index = 1)]
+        id: i64,
+
+        #[vdb_value(index = 2)]
+        score: i64,
+
+        #[vdb_value(index = 4)]
+        tags: Vec<String>,
+    }
+
+    let round_trip = |record: &Record| {
+        let buffer = record.to_vec();
+        assert_eq!(&Record::from_slice(&buffer).unwrap(), record);
+        // dynamic values keep null markers
+        let st = DynamicStruct::from_slice(&buffer).unwrap();
+        assert_eq!(st.to_vec(), buffer);
+        buffer
+    };
+
+    let empty = round_trip(&Record::default());
+    // None fields are omitted
+    assert!(DynamicStruct::from_slice(&empty).unwrap().get(2).is_none());
+
+    round_trip(&Record {
+        id: 1,
+        score: Some(0),
+        inner: Some(Inner { note: None }),
+        tags: vec![None, Some("a".to_string()), None],
+        matrix: vec![Some(vec![Some(1), None]), None, Some(vec![])],
+        attrs: BTreeMap::from([("a".to_string(), None), ("b".to_string(), Some(1.5))]),
+        ..Default::default()
+    });
+    round_trip(&Record {
+        inner: Some(Inner {
+            note: Some("n".to_string()),
+        }),
+        ..Default::default()
+    });
+
+    // nested options keep `Some(None)` apart from `None` and `Some(Some(_))`
+    round_trip(&Record {
+        patch: Some(None),
+        patches: vec![None, Some(None), Some(Some("".to_string()))],
+        ..Default::default()
+    });
+    round_trip(&Record {
+        patch: Some(Some(0)),
+        ..Default::default()
+    });
+
+    let v1 = RecordV1 {
+        id: 2,
+        score: 0,
+        tags: vec!["x".to_string()],
+    };
+    assert_eq!(
+        Record::from_slice(&v1.to_vec()).unwrap(),
+        Record {
+            id: 2,
+            score: Some(0),
+            tags: vec![Some("x".to_string())],
+            ..Default::default()
+        }
+    );
+
+    // schema
+    let schema = Record::schema();
+    assert_eq!(TypeSchema::from_slice(&schema.to_vec()).unwrap(), schema);
+    match &schema {
+        TypeSchema::Struct(schema) => {
+            assert_eq!(
+                schema.field(2).unwrap().schema,
+                TypeSchema::Optional(Box::new(TypeSchema::I64))
+            );
+            assert_eq!(schema.field(2).unwrap().schema.ty(), vdb_value::Ty::I64);
+            assert_eq!(
+                schema.field(7).unwrap().schema.ty(),
+                vdb_value::Ty::Optional
+            );
+        }
+        schema => panic!("{:?}", schema),
+    }
+    let upgrade = vdb_value::schema::check_compatible(&RecordV1::schema(), &schema);
+    assert!(upgrade.iter().all(|change| !change.is_breaking()));
+    let downgrade = vdb_value::schema::check_compatible(&schema, &RecordV1::schema());
+    assert!(downgrade.iter().any(|change| change.is_breaking()));
+}
@@ -6,6 +6,18 @@ pub enum Error {
     #[error("[vdb] Decode error, invalid type")]
     DecodeInvalidType,
 
+    #[error("[vdb] Decode error, value out of range")]
+    DecodeOutOfRange,
+
+    #[error("[vdb] Decode error, invalid length")]
+    DecodeInvalidLength,
+
     #[error("[vdb] String encoding error")]
     String,
+
+    #[error("[vdb] Decode error, unknown enum variant {0}")]
+    DecodeUnknownVariant(u8),
+
+    #[error("[vdb] Invalid schema")]
+    InvalidSchema,
 }
@@ -0,0 +1,328 @@
+//! Value impls of more std types, mapped to existing wire types:
+//! bool and integers are I64, f32 is F64, byte arrays are Bytes, sets are
+//! List, tuples are Struct with fields indexed by position from 1, and maps
+//! are Map. `Vec<u8>` stays Bytes, see `Value::list_ty`.
+//!
+//! note: with `Value` in scope, `b"..".to_vec()` resolves to `Value::to_vec`
+//! of the byte array, use `b".."[..].to_vec()` to copy raw bytes
+use super::*;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::hash::Hash;
+
+impl Value for bool {
+    fn ty(&self) -> Ty {
+        Ty::I64
+    }
+
+    fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+        *self = match input.read_i64()? {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::DecodeOutOfRange),
+        };
+        Ok(())
+    }
+
+    fn to_output(&self, output: &mut OutProtocol<'_>) {
+        output.write_i64(*self as i64);
+    }
+}
+
+macro_rules! impl_value_for_int {
+    ($($ty:ty => $schema:ident),*) => {
+        $(
+            impl Value for $ty {
+                fn ty(&self) -> Ty {
+                    Ty::I64
+                }
+
+                fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+                    *self = <$ty>::try_from(input.read_i64()?).map_err(|_| Error::DecodeOutOfRange)?;
+                    Ok(())
+                }
+
+                fn to_output(&self, output: &mut OutProtocol<'_>) {
+                    output.write_i64(*self as i64);
+                }
+            }
+
+            impl Schema for $ty {
+                fn schema() -> TypeSchema {
+                    TypeSchema::$schema
+                }
+            }
+        )*
+    };
+}
+
+impl_value_for_int!(i8 => I8, i16 => I16, i32 => I32, u16 => U16, u32 => U32);
+
+/// range checked like other integers, a list of u8 is Bytes
+impl Value for u8 {
+    fn ty(&self) -> Ty {
+        Ty::I64
+    }
+
+    fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+        *self = u8::try_from(input.read_i64()?).map_err(|_| Error::DecodeOutOfRange)?;
+        Ok(())
+    }
+
+    fn to_output(&self, output: &mut OutProtocol<'_>) {
+        output.write_i64(*self as i64);
+    }
+
+    fn list_ty() -> Ty {
+        Ty::Bytes
+    }
+
+    fn list_from_input(input: &mut InputProtocol<'_>) -> Result<Vec<Self>, Error> {
+        Ok(input.read_bytes()?.to_vec())
+    }
+
+    fn list_to_output(items: &[Self], output: &mut OutProtocol<'_>) {
+        output.write_bytes(items);
+    }
+}
+
+impl Schema for u8 {
+    fn schema() -> TypeSchema {
+        TypeSchema::U8
+    }
+
+    fn list_schema() -> TypeSchema {
+        TypeSchema::Bytes
+    }
+}
+
+/// stored as i64 with the same bits, values above `i64::MAX` are negative
+/// on the wire. Schema check reports a change between i64 and u64
+impl Value for u64 {
+    fn ty(&self) -> Ty {
+        Ty::I64
+    }
+
+    fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+        *self = input.read_i64()? as u64;
+        Ok(())
+    }
+
+    fn to_output(&self, output: &mut OutProtocol<'_>) {
+        output.write_i64(*self as i64);
+    }
+}
+
+impl Schema for u64 {
+    fn schema() -> TypeSchema {
+        TypeSchema::U64
+    }
+}
+
+impl Value for f32 {
+    fn ty(&self) -> Ty {
+        Ty::F64
+    }
+
+    fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+        let v = input.read_f64()?;
+        if v.is_finite() && (v as f32).is_infinite() {
+            return Err(Error::DecodeOutOfRange);
+        }
+        *self = v as f32;
+        Ok(())
+    }
+
+    fn to_output(&self, output: &mut OutProtocol<'_>) {
+        output.write_f64(*self as f64);
+    }
+}
+
+impl<T: Value> Value for Box<T> {
+    fn ty(&self) -> Ty {
+        self.as_ref().ty()
+    }
+
+    fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+        self.as_mut().from_input(input)
+    }
+
+    fn to_output(&self, output: &mut OutProtocol<'_>) {
+        self.as_ref().to_output(output);
+    }
+}
+
+/// Bytes of exactly N bytes, other lengths fail to decode
+impl<const N: usize> Value for [u8; N]
+where
+    [u8; N]: Default,
+{
+    fn ty(&self) -> Ty {
+        Ty::Bytes
+    }
+
+    fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+        *self = input
+            .read_bytes()?
+            .try_into()
+            .map_err(|_| Error::DecodeInvalidLength)?;
+        Ok(())
+    }
+
+    fn to_output(&self, output: &mut OutProtocol<'_>) {
+        output.write_bytes(self.as_slice());
+    }
+}
+
+impl<const N: usize> Schema for [u8; N] {
+    fn schema() -> TypeSchema {
+        TypeSchema::Bytes
+    }
+}
+
+macro_rules! impl_value_for_tuple {
+    ($($t:ident $v:ident $i:tt),*) => {
+        impl<$($t: Value),*> Value for ($($t,)*) {
+            fn ty(&self) -> Ty {
+                Ty::Struct
+            }
+
+            fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+                while let Some((ty, index)) = input.read_non_stop_field()? {
+                    match index {
+                        $(
+                            i if i == $i + 1 => self.$i.from_input(input)?,
+                        )*
+                        _ => input.skip_field(ty)?,
+                    }
+                }
+                Ok(())
+            }
+
+            fn to_output(&self, output: &mut OutProtocol<'_>) {
+                $(
+                    if !self.$i.is_absent() {
+                        output.write_field_header(self.$i.ty(), $i + 1);
+                        self.$i.to_output(output);
+                    }
+                )*
+                output.write_stop();
+            }
+        }
+
+        impl<$($t: Schema),*> Schema for ($($t,)*) {
+            fn schema() -> TypeSchema {
+                TypeSchema::Struct(StructSchema {
+                    name: "tuple".to_string(),
+                    fields: vec![$(
+                        FieldSchema {
+                            name: stringify!($i).to_string(),
+                            index: $i + 1,
+                            schema: $t::schema(),
+                        },
+                    )*],
+                })
+            }
+        }
+    };
+}
+
+impl_value_for_tuple!(A a 0, B b 1);
+impl_value_for_tuple!(A a 0, B b 1, C c 2);
+impl_value_for_tuple!(A a 0, B b 1, C c 2, D d 3);
+
+macro_rules! impl_value_for_map {
+    ($map:ident, $($bound:path),*) => {
+        impl<K: Value $(+ $bound)*, V: Value> Value for $map<K, V> {
+            fn ty(&self) -> Ty {
+                Ty::Map
+            }
+
+            fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+                let (key_ty, value_ty, size) = input.read_map_header()?;
+                let mut result = $map::new();
+                for _i in 0..size {
+                    let mut k = K::default();
+                    k.from_item_input(input, key_ty)?;
+                    let mut v = V::default();
+                    v.from_item_input(input, value_ty)?;
+                    result.insert(k, v);
+                }
+                *self = result;
+                Ok(())
+            }
+
+            fn to_output(&self, output: &mut OutProtocol<'_>) {
+                output.write_map_header(K::item_ty(), V::item_ty(), self.len() as u32);
+                for (k, v) in self.iter() {
+                    k.to_item_output(output);
+                    v.to_item_output(output);
+                }
+            }
+        }
+
+        impl<K: Schema, V: Schema> Schema for $map<K, V> {
+            fn schema() -> TypeSchema {
+                TypeSchema::Map(Box::new(K::schema()), Box::new(V::schema()))
+            }
+        }
+    };
+}
+
+impl_value_for_map!(BTreeMap, Ord);
+impl_value_for_map!(HashMap, Eq, Hash);
+
+macro_rules! impl_value_for_set {
+    ($set:ident, $($bound:path),*) => {
+        impl<T: Value $(+ $bound)*> Value for $set<T> {
+            fn ty(&self) -> Ty {
+                Ty::List
+            }
+
+            fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
+                let (item_ty, size) = input.read_list_header()?;
+                let mut result = $set::new();
+                for _i in 0..size {
+                    let mut item = T::default();
+                    item.from_item_input(input, item_ty)?;
+                    result.insert(item);
+                }
+                *self = result;
+                Ok(())
+            }
+
+            fn to_output(&self, output: &mut OutProtocol<'_>) {
+                output.write_list_header(T::item_ty(), self.len() as u32);
+                for item in self.iter() {
+                    item.to_item_output(output);
+                }
+            }
+        }
+
+        impl<T: Schema> Schema for $set<T> {
+            fn schema() -> TypeSchema {
+                TypeSchema::List(Box::new(T::schema()))
+            }
+        }
+    };
+}
+
+impl_value_for_set!(BTreeSet, Ord);
+impl_value_for_set!(HashSet, Eq, Hash);
+
+impl Schema for bool {
+    fn schema() -> TypeSchema {
+        TypeSchema::Bool
+    }
+}
+
+impl Schema for f32 {
+    fn schema() -> TypeSchema {
+        TypeSchema::F64
+    }
+}
+
+impl<T: Schema> Schema for Box<T> {
+    fn schema() -> TypeSchema {
+        T::schema()
+    }
+}
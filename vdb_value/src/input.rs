@@ -50,6 +50,11 @@ impl InputProtocol<'_> {
         Ok(buffer)
     }
 
+    pub fn read_ty(&mut self) -> Result<Ty, Error> {
+        let [ty] = self.read_n_bytes::<1>()?;
+        try_u8_to_ty(ty)
+    }
+
     pub fn read_field_header(&mut self) -> Result<(Ty, u8), Error> {
         let [ty, index] = self.read_n_bytes::<2>()?;
         let ty = try_u8_to_ty(ty)?;
@@ -62,6 +67,15 @@ impl InputProtocol<'_> {
         Ok((ty, self.raw_read_u32()?))
     }
 
+    pub fn read_map_header(&mut self) -> Result<(Ty, Ty, u32), Error> {
+        let [key_ty, value_ty] = self.read_n_bytes::<2>()?;
+        Ok((
+            try_u8_to_ty(key_ty)?,
+            try_u8_to_ty(value_ty)?,
+            self.raw_read_u32()?,
+        ))
+    }
+
     pub fn read_non_stop_field(&mut self) -> Result<Option<(Ty, u8)>, Error> {
         let (ty, index) = self.read_field_header()?;
         if ty == Ty::Stop {
@@ -95,6 +109,19 @@ impl InputProtocol<'_> {
                     self.skip_field(ty)?;
                 }
             }
+            Ty::Optional => {
+                let ty = self.read_ty()?;
+                if ty != Ty::Stop {
+                    self.skip_field(ty)?;
+                }
+            }
+            Ty::Map => {
+                let (key_ty, value_ty, size) = self.read_map_header()?;
+                for _i in 0..size {
+                    self.skip_field(key_ty)?;
+                    self.skip_field(value_ty)?;
+                }
+            }
         };
 
         Ok(())
@@ -13,6 +13,11 @@ pub enum Ty {
     Bytes = 3,
     List = 4,
     Struct = 5,
+    /// key type, value type and size header, followed by key value pairs
+    Map = 6,
+    /// type byte followed by value of the type, `Stop` type means null,
+    /// used by list items and map entries of `Option`
+    Optional = 7,
     // indicate Struct finish
     Stop = 255,
 }
@@ -21,10 +26,58 @@ pub trait Value: Default + Sized {
     /// the wire type for this value
     fn ty(&self) -> Ty;
 
+    #[allow(clippy::wrong_self_convention)]
     fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error>;
 
     fn to_output(&self, output: &mut OutProtocol<'_>);
 
+    /// absent value is omitted from struct fields, e.g: `None`
+    fn is_absent(&self) -> bool {
+        false
+    }
+
+    /// wire type of list items and map entries of this type
+    fn item_ty() -> Ty {
+        Self::default().ty()
+    }
+
+    /// write as list item or map entry
+    fn to_item_output(&self, output: &mut OutProtocol<'_>) {
+        self.to_output(output);
+    }
+
+    /// read as list item or map entry, `item_ty` is from the header
+    #[allow(clippy::wrong_self_convention)]
+    fn from_item_input(&mut self, input: &mut InputProtocol<'_>, item_ty: Ty) -> Result<(), Error> {
+        let _ = item_ty;
+        self.from_input(input)
+    }
+
+    /// wire type of `Vec<Self>`, List unless the items are bytes
+    fn list_ty() -> Ty {
+        Ty::List
+    }
+
+    /// read `Vec<Self>`, overridden with `list_ty` e.g: `Vec<u8>` is Bytes
+    fn list_from_input(input: &mut InputProtocol<'_>) -> Result<Vec<Self>, Error> {
+        let (item_ty, size) = input.read_list_header()?;
+        let mut result = Vec::with_capacity(size as usize);
+        for _i in 0..size {
+            let mut item_val = Self::default();
+            item_val.from_item_input(input, item_ty)?;
+            result.push(item_val);
+        }
+        Ok(result)
+    }
+
+    /// write `Vec<Self>`, see `list_from_input`
+    fn list_to_output(items: &[Self], output: &mut OutProtocol<'_>) {
+        output.write_list_header(Self::item_ty(), items.len() as u32);
+        for item in items.iter() {
+            item.to_item_output(output);
+        }
+    }
+
     /// load from slice
     fn from_slice(slice: &[u8]) -> Result<Self, Error> {
         let mut input = InputProtocol::new(slice);
@@ -51,6 +104,11 @@ pub use input::*;
 mod value;
 pub use value::*;
 
+mod impls;
+
+pub mod schema;
+pub use schema::{FieldSchema, Schema, StructSchema, TypeSchema};
+
 #[cfg(test)]
 mod tests;
 
@@ -68,12 +126,14 @@ pub(crate) fn try_u8_to_ty(val: u8) -> Result<Ty, Error> {
         3 => Ty::Bytes,
         4 => Ty::List,
         5 => Ty::Struct,
+        6 => Ty::Map,
+        7 => Ty::Optional,
         255 => Ty::Stop,
         _ => return Err(Error::DecodeInvalidType),
     })
 }
 
-// re-export Value derived macro
+// re-export Value derived macro, it also implements Schema
 pub use vdb_derive::Value;
 
 impl Value for i64 {
@@ -106,21 +166,6 @@ impl Value for f64 {
     }
 }
 
-impl Value for Vec<u8> {
-    fn ty(&self) -> Ty {
-        Ty::Bytes
-    }
-
-    fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
-        *self = input.read_bytes()?.to_vec();
-        Ok(())
-    }
-
-    fn to_output(&self, output: &mut OutProtocol<'_>) {
-        output.write_bytes(self.as_slice())
-    }
-}
-
 impl Value for String {
     fn ty(&self) -> Ty {
         Ty::Bytes
@@ -136,50 +181,73 @@ impl Value for String {
     }
 }
 
+/// List of items, `Vec<u8>` is Bytes, see `Value::list_ty`
 impl<T: Value> Value for Vec<T> {
     fn ty(&self) -> Ty {
-        Ty::List
+        T::list_ty()
     }
 
     fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
-        let (_item_ty, size) = input.read_list_header()?;
-        let mut result = Vec::with_capacity(size as usize);
-        for _i in 0..size {
-            let mut item_val = T::default();
-            item_val.from_input(input)?;
-            result.push(item_val);
-        }
-        *self = result;
+        *self = T::list_from_input(input)?;
         Ok(())
     }
 
     fn to_output(&self, output: &mut OutProtocol<'_>) {
-        let item_ty = T::default().ty();
-        output.write_list_header(item_ty, self.len() as u32);
-        for item in self.iter() {
-            item.to_output(output);
-        }
+        T::list_to_output(self.as_slice(), output);
     }
 }
 
+/// `None` field is omitted from struct, absent field decodes as `None`.
+/// In lists and maps, items are `Ty::Optional` with an explicit null marker,
+/// items written before as plain `T` decode as `Some`. If `T` is itself an
+/// `Option`, the value is `Ty::Optional` too, so `Some(None)` round trips
 impl<T: Value> Value for Option<T> {
     fn ty(&self) -> Ty {
-        T::default().ty()
+        if T::item_ty() == Ty::Optional {
+            Ty::Optional
+        } else {
+            T::default().ty()
+        }
     }
 
     fn from_input(&mut self, input: &mut InputProtocol<'_>) -> Result<(), Error> {
         let mut value = T::default();
-        value.from_input(input)?;
+        value.from_item_input(input, self.ty())?;
         *self = Some(value);
         Ok(())
     }
 
     fn to_output(&self, output: &mut OutProtocol<'_>) {
-        if let Some(ref val) = self {
-            val.to_output(output);
-        } else {
-            let val = T::default();
-            val.to_output(output);
+        match self {
+            Some(val) if T::item_ty() == Ty::Optional => val.to_item_output(output),
+            Some(val) => val.to_output(output),
+            None => T::default().to_item_output(output),
+        }
+    }
+
+    fn is_absent(&self) -> bool {
+        self.is_none()
+    }
+
+    fn item_ty() -> Ty {
+        Ty::Optional
+    }
+
+    fn to_item_output(&self, output: &mut OutProtocol<'_>) {
+        match self {
+            Some(_) => {
+                output.write_ty(self.ty());
+                self.to_output(output);
+            }
+            None => output.write_ty(Ty::Stop),
+        }
+    }
+
+    fn from_item_input(&mut self, input: &mut InputProtocol<'_>, item_ty: Ty) -> Result<(), Error> {
+        if item_ty == Ty::Optional && input.read_ty()? == Ty::Stop {
+            *self = None;
+            return Ok(());
         }
+        self.from_input(input)
     }
 }
@@ -28,6 +28,10 @@ impl OutProtocol<'_> {
         self.store.extend_from_slice(bytes);
     }
 
+    pub fn write_ty(&mut self, ty: Ty) {
+        self.store.push(ty_to_u8(ty));
+    }
+
     /// write field type and index, each took 1 byte
     pub fn write_field_header(&mut self, ty: Ty, index: u8) {
         self.store.push(ty_to_u8(ty));
@@ -39,6 +43,12 @@ impl OutProtocol<'_> {
         self.write_u32(item_size);
     }
 
+    pub fn write_map_header(&mut self, key_ty: Ty, value_ty: Ty, size: u32) {
+        self.store.push(ty_to_u8(key_ty));
+        self.store.push(ty_to_u8(value_ty));
+        self.write_u32(size);
+    }
+
     pub fn write_stop(&mut self) {
         self.write_field_header(Ty::Stop, 255);
     }
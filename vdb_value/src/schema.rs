@@ -0,0 +1,442 @@
+use super::*;
+
+/// Type with a static schema, implemented by `#[derive(Value)]`
+pub trait Schema {
+    fn schema() -> TypeSchema;
+
+    /// schema of `Vec<Self>`, see `Value::list_ty`
+    fn list_schema() -> TypeSchema {
+        TypeSchema::List(Box::new(Self::schema()))
+    }
+}
+
+/// Schema of a value type
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeSchema {
+    I64,
+    /// integers narrower than i64 and bool are I64 on the wire, range
+    /// checked on decode
+    Bool,
+    I8,
+    I16,
+    I32,
+    U8,
+    U16,
+    U32,
+    /// I64 on the wire with the same bits, values above `i64::MAX` are
+    /// negative there
+    U64,
+    F64,
+    Bytes,
+    /// utf8 bytes on the wire
+    String,
+    List(Box<TypeSchema>),
+    Struct(StructSchema),
+    /// key and value schema
+    Map(Box<TypeSchema>, Box<TypeSchema>),
+    /// `Option` of the schema, absent as struct field
+    Optional(Box<TypeSchema>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructSchema {
+    pub name: String,
+    pub fields: Vec<FieldSchema>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldSchema {
+    pub name: String,
+    pub index: u8,
+    pub schema: TypeSchema,
+}
+
+// field indexes of exported schema
+const KIND: u8 = 1;
+const NAME: u8 = 2;
+const FIELDS: u8 = 3;
+const ITEM: u8 = 4;
+const KEY: u8 = 5;
+
+const FIELD_NAME: u8 = 1;
+const FIELD_INDEX: u8 = 2;
+const FIELD_SCHEMA: u8 = 3;
+
+impl TypeSchema {
+    /// the wire type
+    pub fn ty(&self) -> Ty {
+        match self {
+            TypeSchema::I64
+            | TypeSchema::Bool
+            | TypeSchema::I8
+            | TypeSchema::I16
+            | TypeSchema::I32
+            | TypeSchema::U8
+            | TypeSchema::U16
+            | TypeSchema::U32
+            | TypeSchema::U64 => Ty::I64,
+            TypeSchema::F64 => Ty::F64,
+            TypeSchema::Bytes | TypeSchema::String => Ty::Bytes,
+            TypeSchema::List(_) => Ty::List,
+            TypeSchema::Struct(_) => Ty::Struct,
+            TypeSchema::Map(..) => Ty::Map,
+            // only nested option is written as optional
+            TypeSchema::Optional(inner) => match inner.as_ref() {
+                TypeSchema::Optional(_) => Ty::Optional,
+                inner => inner.ty(),
+            },
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            TypeSchema::I64 => "i64",
+            TypeSchema::Bool => "bool",
+            TypeSchema::I8 => "i8",
+            TypeSchema::I16 => "i16",
+            TypeSchema::I32 => "i32",
+            TypeSchema::U8 => "u8",
+            TypeSchema::U16 => "u16",
+            TypeSchema::U32 => "u32",
+            TypeSchema::U64 => "u64",
+            TypeSchema::F64 => "f64",
+            TypeSchema::Bytes => "bytes",
+            TypeSchema::String => "string",
+            TypeSchema::List(_) => "list",
+            TypeSchema::Struct(_) => "struct",
+            TypeSchema::Map(..) => "map",
+            TypeSchema::Optional(_) => "optional",
+        }
+    }
+
+    /// export as dynamic value, a struct of `1: kind`, `2: struct name`,
+    /// `3: fields`, `4: list item, map value or optional inner schema` and
+    /// `5: map key schema`, each field is a struct of
+    /// `1: name`, `2: index` and `3: schema`
+    pub fn to_dynamic(&self) -> DynamicValue {
+        let mut st = DynamicStruct::default();
+        st.insert(KIND, bytes_value(self.kind()));
+        match self {
+            TypeSchema::List(item) | TypeSchema::Optional(item) => {
+                st.insert(ITEM, item.to_dynamic());
+            }
+            TypeSchema::Map(key, value) => {
+                st.insert(KEY, key.to_dynamic());
+                st.insert(ITEM, value.to_dynamic());
+            }
+            TypeSchema::Struct(schema) => {
+                st.insert(NAME, bytes_value(&schema.name));
+                let fields = schema
+                    .fields
+                    .iter()
+                    .map(|field| {
+                        let mut field_st = DynamicStruct::default();
+                        field_st.insert(FIELD_NAME, bytes_value(&field.name));
+                        field_st.insert(FIELD_INDEX, DynamicValue::I64(field.index as i64));
+                        field_st.insert(FIELD_SCHEMA, field.schema.to_dynamic());
+                        DynamicValue::Struct(Box::new(field_st))
+                    })
+                    .collect();
+                st.insert(
+                    FIELDS,
+                    DynamicValue::List {
+                        item_ty: Ty::Struct,
+                        items: Box::new(fields),
+                    },
+                );
+            }
+            _ => {}
+        }
+        DynamicValue::Struct(Box::new(st))
+    }
+
+    /// exported schema bytes, e.g: to be checked in and compared by
+    /// `check_compatible` later
+    pub fn to_vec(&self) -> Vec<u8> {
+        self.to_dynamic().to_vec()
+    }
+
+    pub fn from_slice(slice: &[u8]) -> Result<Self, Error> {
+        let mut value = DynamicValue::default_for_ty(Ty::Struct);
+        value.from_input(&mut InputProtocol::new(slice))?;
+        Self::from_dynamic(&value)
+    }
+
+    /// load exported schema, see `to_dynamic`
+    pub fn from_dynamic(value: &DynamicValue) -> Result<Self, Error> {
+        let st = as_struct(Some(value))?;
+        Ok(match as_str(st.get(KIND))? {
+            "i64" => TypeSchema::I64,
+            "bool" => TypeSchema::Bool,
+            "i8" => TypeSchema::I8,
+            "i16" => TypeSchema::I16,
+            "i32" => TypeSchema::I32,
+            "u8" => TypeSchema::U8,
+            "u16" => TypeSchema::U16,
+            "u32" => TypeSchema::U32,
+            "u64" => TypeSchema::U64,
+            "f64" => TypeSchema::F64,
+            "bytes" => TypeSchema::Bytes,
+            "string" => TypeSchema::String,
+            "list" => TypeSchema::List(Box::new(Self::from_dynamic(
+                st.get(ITEM).ok_or(Error::InvalidSchema)?,
+            )?)),
+            "optional" => TypeSchema::Optional(Box::new(Self::from_dynamic(
+                st.get(ITEM).ok_or(Error::InvalidSchema)?,
+            )?)),
+            "map" => TypeSchema::Map(
+                Box::new(Self::from_dynamic(
+                    st.get(KEY).ok_or(Error::InvalidSchema)?,
+                )?),
+                Box::new(Self::from_dynamic(
+                    st.get(ITEM).ok_or(Error::InvalidSchema)?,
+                )?),
+            ),
+            "struct" => {
+                let items = match st.get(FIELDS) {
+                    Some(DynamicValue::List { items, .. }) => items,
+                    _ => return Err(Error::InvalidSchema),
+                };
+                let fields = items
+                    .iter()
+                    .map(|item| {
+                        let field_st = as_struct(Some(item))?;
+                        Ok(FieldSchema {
+                            name: as_str(field_st.get(FIELD_NAME))?.to_string(),
+                            index: match field_st.get(FIELD_INDEX) {
+                                Some(DynamicValue::I64(index)) => {
+                                    u8::try_from(*index).map_err(|_| Error::InvalidSchema)?
+                                }
+                                _ => return Err(Error::InvalidSchema),
+                            },
+                            schema: Self::from_dynamic(
+                                field_st.get(FIELD_SCHEMA).ok_or(Error::InvalidSchema)?,
+                            )?,
+                        })
+                    })
+                    .collect::<Result<_, Error>>()?;
+                TypeSchema::Struct(StructSchema {
+                    name: as_str(st.get(NAME))?.to_string(),
+                    fields,
+                })
+            }
+            _ => return Err(Error::InvalidSchema),
+        })
+    }
+
+    /// value range of integer schemas, None for others
+    fn int_range(&self) -> Option<(i128, i128)> {
+        Some(match self {
+            TypeSchema::I64 => (i64::MIN as i128, i64::MAX as i128),
+            TypeSchema::Bool => (0, 1),
+            TypeSchema::I8 => (i8::MIN as i128, i8::MAX as i128),
+            TypeSchema::I16 => (i16::MIN as i128, i16::MAX as i128),
+            TypeSchema::I32 => (i32::MIN as i128, i32::MAX as i128),
+            TypeSchema::U8 => (0, u8::MAX as i128),
+            TypeSchema::U16 => (0, u16::MAX as i128),
+            TypeSchema::U32 => (0, u32::MAX as i128),
+            TypeSchema::U64 => (0, u64::MAX as i128),
+            _ => return None,
+        })
+    }
+
+    /// every value of self is a value of other with the same wire bits
+    fn int_widens_to(&self, other: &TypeSchema) -> bool {
+        match (self.int_range(), other.int_range()) {
+            (Some((lower, higher)), Some((other_lower, other_higher))) => {
+                other_lower <= lower && higher <= other_higher
+            }
+            _ => false,
+        }
+    }
+}
+
+impl StructSchema {
+    pub fn field(&self, index: u8) -> Option<&FieldSchema> {
+        self.fields.iter().find(|field| field.index == index)
+    }
+
+    pub fn field_by_name(&self, name: &str) -> Option<&FieldSchema> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+fn bytes_value(s: &str) -> DynamicValue {
+    DynamicValue::Bytes(Box::new(s.as_bytes().to_vec()))
+}
+
+fn as_struct(value: Option<&DynamicValue>) -> Result<&DynamicStruct, Error> {
+    match value {
+        Some(DynamicValue::Struct(st)) => Ok(st),
+        _ => Err(Error::InvalidSchema),
+    }
+}
+
+fn as_str(value: Option<&DynamicValue>) -> Result<&str, Error> {
+    match value {
+        Some(DynamicValue::Bytes(bytes)) => {
+            std::str::from_utf8(bytes).map_err(|_| Error::InvalidSchema)
+        }
+        _ => Err(Error::InvalidSchema),
+    }
+}
+
+impl Schema for i64 {
+    fn schema() -> TypeSchema {
+        TypeSchema::I64
+    }
+}
+
+impl Schema for f64 {
+    fn schema() -> TypeSchema {
+        TypeSchema::F64
+    }
+}
+
+impl Schema for String {
+    fn schema() -> TypeSchema {
+        TypeSchema::String
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> TypeSchema {
+        T::list_schema()
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> TypeSchema {
+        TypeSchema::Optional(Box::new(T::schema()))
+    }
+}
+
+/// Change between two schemas, `path` is the field indexes from the root
+/// struct, fields of list items continue the path of the list field
+#[derive(Debug, Clone, PartialEq)]
+pub enum SchemaChange {
+    FieldAdded {
+        path: Vec<u8>,
+        name: String,
+    },
+    FieldRemoved {
+        path: Vec<u8>,
+        name: String,
+    },
+    /// field keeps its index and a compatible type under a new name
+    FieldRenamed {
+        path: Vec<u8>,
+        old_name: String,
+        new_name: String,
+    },
+    /// values written as old type can't be read as new type
+    TypeChanged {
+        path: Vec<u8>,
+        old: TypeSchema,
+        new: TypeSchema,
+    },
+    /// index of a removed field is taken by another field of incompatible
+    /// type, old values would be read as the new field
+    IndexReused {
+        path: Vec<u8>,
+        old_name: String,
+        new_name: String,
+    },
+}
+
+impl SchemaChange {
+    pub fn is_breaking(&self) -> bool {
+        match self {
+            SchemaChange::FieldAdded { .. }
+            | SchemaChange::FieldRemoved { .. }
+            | SchemaChange::FieldRenamed { .. } => false,
+            SchemaChange::TypeChanged { .. } | SchemaChange::IndexReused { .. } => true,
+        }
+    }
+}
+
+/// compare schema of stored values with a new schema, returns all changes,
+/// check `SchemaChange::is_breaking` for the ones corrupting data
+pub fn check_compatible(old: &TypeSchema, new: &TypeSchema) -> Vec<SchemaChange> {
+    let mut changes = vec![];
+    compare(old, new, &mut vec![], &mut changes);
+    changes
+}
+
+fn compare(
+    old: &TypeSchema,
+    new: &TypeSchema,
+    path: &mut Vec<u8>,
+    changes: &mut Vec<SchemaChange>,
+) {
+    match (old, new) {
+        (TypeSchema::Struct(old), TypeSchema::Struct(new)) => {
+            for old_field in old.fields.iter() {
+                path.push(old_field.index);
+                match new.field(old_field.index) {
+                    None => changes.push(SchemaChange::FieldRemoved {
+                        path: path.clone(),
+                        name: old_field.name.clone(),
+                    }),
+                    Some(new_field) if new_field.name != old_field.name => {
+                        let mut field_changes = vec![];
+                        compare(
+                            &old_field.schema,
+                            &new_field.schema,
+                            path,
+                            &mut field_changes,
+                        );
+                        if field_changes.iter().any(SchemaChange::is_breaking) {
+                            changes.push(SchemaChange::IndexReused {
+                                path: path.clone(),
+                                old_name: old_field.name.clone(),
+                                new_name: new_field.name.clone(),
+                            })
+                        } else {
+                            changes.push(SchemaChange::FieldRenamed {
+                                path: path.clone(),
+                                old_name: old_field.name.clone(),
+                                new_name: new_field.name.clone(),
+                            });
+                            changes.extend(field_changes);
+                        }
+                    }
+                    Some(new_field) => compare(&old_field.schema, &new_field.schema, path, changes),
+                }
+                path.pop();
+            }
+
+            for new_field in new.fields.iter() {
+                if old.field(new_field.index).is_none() {
+                    path.push(new_field.index);
+                    changes.push(SchemaChange::FieldAdded {
+                        path: path.clone(),
+                        name: new_field.name.clone(),
+                    });
+                    path.pop();
+                }
+            }
+        }
+        (TypeSchema::List(old), TypeSchema::List(new)) => compare(old, new, path, changes),
+        (TypeSchema::Optional(old), TypeSchema::Optional(new)) => compare(old, new, path, changes),
+        // plain `T` decodes as `Some`, unless `T` is an option. The other way
+        // around breaks list items and map entries, written with null markers
+        (old, TypeSchema::Optional(new)) if !matches!(**new, TypeSchema::Optional(_)) => {
+            compare(old, new, path, changes)
+        }
+        (TypeSchema::Map(old_key, old_value), TypeSchema::Map(new_key, new_value)) => {
+            compare(old_key, new_key, path, changes);
+            compare(old_value, new_value, path, changes);
+        }
+        // any bytes can be read as bytes
+        (TypeSchema::Bytes | TypeSchema::String, TypeSchema::Bytes) => {}
+        // narrowing an integer makes out of range values fail to decode
+        (old, new) if old.int_widens_to(new) => {}
+        (old, new) if old == new => {}
+        (old, new) => changes.push(SchemaChange::TypeChanged {
+            path: path.clone(),
+            old: old.clone(),
+            new: new.clone(),
+        }),
+    }
+}
@@ -1,5 +1,8 @@
 use super::{InputProtocol, OutProtocol, Ty};
-use crate::{try_u8_to_ty, DynamicStruct, DynamicValue, Error, Value};
+use crate::{
+    try_u8_to_ty, DynamicStruct, DynamicValue, Error, FieldSchema, Schema, StructSchema,
+    TypeSchema, Value,
+};
 use rand::{thread_rng, Rng};
 
 #[test]
@@ -41,12 +44,12 @@ fn test_input_output() {
 
 fn rand_ty(allow_container: bool) -> Ty {
     loop {
-        let ty = try_u8_to_ty(rand::thread_rng().gen_range(1..6u8)).unwrap();
+        let ty = try_u8_to_ty(rand::thread_rng().gen_range(1..8u8)).unwrap();
         match ty {
             Ty::Any | Ty::Stop => {
                 unreachable!()
             }
-            Ty::List | Ty::Struct => {
+            Ty::List | Ty::Struct | Ty::Map | Ty::Optional => {
                 if allow_container {
                     return ty;
                 }
@@ -97,6 +100,29 @@ fn rand_value_for_ty(ty: Ty, depth: u8) -> DynamicValue {
             }
         }
         Ty::Struct => DynamicValue::Struct(Box::new(rand_struct(depth + 1))),
+        Ty::Map => {
+            let key_ty = rand_ty(false);
+            let value_ty = rand_ty(depth < 10);
+            let size = thread_rng().gen_range(0..8);
+            let entries = (0..size)
+                .map(|_| {
+                    (
+                        rand_value_for_ty(key_ty, depth + 1),
+                        rand_value_for_ty(value_ty, depth + 1),
+                    )
+                })
+                .collect::<Vec<_>>();
+            DynamicValue::Map {
+                key_ty,
+                value_ty,
+                entries: Box::new(entries),
+            }
+        }
+        Ty::Optional => DynamicValue::Optional(
+            thread_rng()
+                .gen_bool(0.5)
+                .then(|| Box::new(rand_value_for_ty(rand_ty(depth < 10), depth + 1))),
+        ),
         Ty::Stop => {
             unreachable!()
         }
@@ -161,9 +187,9 @@ fn manual_impl_value() {
         }
     }
 
-    let mut model = TestModel {
+    let model = TestModel {
         val_1: 12345,
-        val_s: b"foo bar".to_vec(),
+        val_s: b"foo bar"[..].to_vec(),
     };
 
     let buf = dbg!(model.to_vec());
@@ -171,3 +197,270 @@ fn manual_impl_value() {
 
     assert_eq!(back_model, model);
 }
+
+#[test]
+fn test_schema_dynamic_round_trip() {
+    let schema = TypeSchema::Struct(StructSchema {
+        name: "User".to_string(),
+        fields: vec![
+            FieldSchema {
+                name: "id".to_string(),
+                index: 1,
+                schema: i64::schema(),
+            },
+            FieldSchema {
+                name: "tags".to_string(),
+                index: 2,
+                schema: Vec::<String>::schema(),
+            },
+            FieldSchema {
+                name: "avatar".to_string(),
+                index: 3,
+                schema: Option::<Vec<u8>>::schema(),
+            },
+        ],
+    });
+
+    let exported = schema.to_dynamic();
+    let mut loaded = DynamicValue::default_for_ty(Ty::Struct);
+    loaded
+        .from_input(&mut InputProtocol::new(&exported.to_vec()))
+        .unwrap();
+    assert_eq!(TypeSchema::from_dynamic(&loaded).unwrap(), schema);
+
+    assert!(matches!(
+        TypeSchema::from_dynamic(&DynamicValue::I64(1)),
+        Err(Error::InvalidSchema)
+    ));
+}
+
+#[test]
+fn test_schema_check_compatible() {
+    use crate::schema::{check_compatible, SchemaChange};
+
+    let field = |name: &str, index: u8, schema: TypeSchema| FieldSchema {
+        name: name.to_string(),
+        index,
+        schema,
+    };
+    let user = |fields: Vec<FieldSchema>| {
+        TypeSchema::Struct(StructSchema {
+            name: "User".to_string(),
+            fields,
+        })
+    };
+    let address = |fields: Vec<FieldSchema>| {
+        TypeSchema::List(Box::new(TypeSchema::Struct(StructSchema {
+            name: "Address".to_string(),
+            fields,
+        })))
+    };
+
+    // previous schema, as checked in
+    let old = TypeSchema::from_slice(
+        &user(vec![
+            field("id", 1, TypeSchema::I64),
+            field("email", 2, TypeSchema::String),
+            field("score", 3, TypeSchema::I64),
+            field(
+                "addresses",
+                4,
+                address(vec![field("zip", 1, TypeSchema::I64)]),
+            ),
+        ])
+        .to_vec(),
+    )
+    .unwrap();
+
+    let compatible = user(vec![
+        field("id", 1, TypeSchema::I64),
+        field("email", 2, TypeSchema::Bytes),
+        field(
+            "addresses",
+            4,
+            address(vec![field("zip", 1, TypeSchema::I64)]),
+        ),
+        field("name", 5, TypeSchema::String),
+    ]);
+    assert_eq!(
+        check_compatible(&old, &compatible),
+        vec![
+            SchemaChange::FieldRemoved {
+                path: vec![3],
+                name: "score".to_string(),
+            },
+            SchemaChange::FieldAdded {
+                path: vec![5],
+                name: "name".to_string(),
+            },
+        ]
+    );
+    assert!(check_compatible(&old, &compatible)
+        .iter()
+        .all(|change| !change.is_breaking()));
+
+    // same index and compatible type under a new name
+    let renamed = user(vec![
+        field("id", 1, TypeSchema::I64),
+        field("email", 2, TypeSchema::String),
+        field("points", 3, TypeSchema::I64),
+        field(
+            "addresses",
+            4,
+            address(vec![field("zip", 1, TypeSchema::I64)]),
+        ),
+    ]);
+    assert_eq!(
+        check_compatible(&old, &renamed),
+        vec![SchemaChange::FieldRenamed {
+            path: vec![3],
+            old_name: "score".to_string(),
+            new_name: "points".to_string(),
+        }]
+    );
+    assert!(!check_compatible(&old, &renamed)[0].is_breaking());
+
+    let breaking = user(vec![
+        field("id", 1, TypeSchema::F64),
+        field("email", 2, TypeSchema::String),
+        field("rank", 3, TypeSchema::String),
+        field(
+            "addresses",
+            4,
+            address(vec![field("zip", 1, TypeSchema::String)]),
+        ),
+    ]);
+    assert_eq!(
+        check_compatible(&old, &breaking),
+        vec![
+            SchemaChange::TypeChanged {
+                path: vec![1],
+                old: TypeSchema::I64,
+                new: TypeSchema::F64,
+            },
+            SchemaChange::IndexReused {
+                path: vec![3],
+                old_name: "score".to_string(),
+                new_name: "rank".to_string(),
+            },
+            SchemaChange::TypeChanged {
+                path: vec![4, 1],
+                old: TypeSchema::I64,
+                new: TypeSchema::String,
+            },
+        ]
+    );
+
+    // integers may widen, narrowing fails to decode out of range values
+    assert!(check_compatible(&u8::schema(), &i16::schema()).is_empty());
+    assert!(check_compatible(&bool::schema(), &i64::schema()).is_empty());
+    assert!(check_compatible(&u32::schema(), &u64::schema()).is_empty());
+    for (old, new) in [
+        (i64::schema(), i8::schema()),
+        (i64::schema(), bool::schema()),
+        (i8::schema(), u8::schema()),
+        (i64::schema(), u64::schema()),
+        (u64::schema(), i64::schema()),
+    ] {
+        assert_eq!(
+            check_compatible(&old, &new),
+            vec![SchemaChange::TypeChanged {
+                path: vec![],
+                old: old.clone(),
+                new: new.clone(),
+            }]
+        );
+    }
+    assert_eq!(
+        TypeSchema::from_slice(&u16::schema().to_vec()).unwrap(),
+        TypeSchema::U16
+    );
+}
+
+#[test]
+fn test_std_type_values() {
+    use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+    fn round_trip<T: Value + PartialEq + std::fmt::Debug>(value: T) {
+        assert_eq!(T::from_slice(&value.to_vec()).unwrap(), value);
+    }
+
+    round_trip(true);
+    round_trip(-3i8);
+    round_trip(i16::MIN);
+    round_trip(i32::MAX);
+    round_trip(u16::MAX);
+    round_trip(u32::MAX);
+    round_trip(u8::MAX);
+    round_trip(u64::MAX);
+    round_trip(1.5f32);
+    round_trip([1u8, 2, 3, 4]);
+    round_trip(vec![1u8, 2, 3]);
+    round_trip(Box::new(7i64));
+    round_trip((1i64, "a".to_string()));
+    round_trip((1i64, 2.5f64, vec![true, false], Some(3i32)));
+    round_trip(BTreeMap::from([
+        ("a".to_string(), 1i64),
+        ("b".to_string(), 2),
+    ]));
+    round_trip(HashMap::from([(1u32, vec![1.5f64]), (2, vec![])]));
+    round_trip(BTreeSet::from([3i64, 1, 2]));
+    round_trip(HashSet::from(["x".to_string()]));
+    round_trip(BTreeMap::from([(
+        1i64,
+        BTreeMap::from([(2i64, (3i64, 4i64))]),
+    )]));
+
+    // range checked on decode
+    assert!(matches!(
+        u16::from_slice(&(-1i64).to_vec()),
+        Err(Error::DecodeOutOfRange)
+    ));
+    assert!(matches!(
+        bool::from_slice(&2i64.to_vec()),
+        Err(Error::DecodeOutOfRange)
+    ));
+    assert!(matches!(
+        f32::from_slice(&f64::MAX.to_vec()),
+        Err(Error::DecodeOutOfRange)
+    ));
+    assert!(matches!(
+        u8::from_slice(&256i64.to_vec()),
+        Err(Error::DecodeOutOfRange)
+    ));
+    assert!(matches!(
+        <[u8; 4]>::from_slice(&vec![1u8, 2, 3].to_vec()),
+        Err(Error::DecodeInvalidLength)
+    ));
+
+    // u8 list is still bytes, u64 keeps its bits on the I64 wire
+    assert_eq!(
+        vec![1u8, 2].to_vec(),
+        b"\x00\x00\x00\x02\x01\x02"[..].to_vec()
+    );
+    assert_eq!(u64::MAX.to_vec(), (-1i64).to_vec());
+    assert_eq!(Vec::<u8>::schema(), TypeSchema::Bytes);
+    assert_eq!(<[u8; 4]>::schema(), TypeSchema::Bytes);
+
+    // maps are skipped by dynamic values, and survive a dynamic round trip
+    let map = BTreeMap::from([(1i64, "a".to_string())]);
+    let mut value = DynamicValue::default_for_ty(Ty::Map);
+    value
+        .from_input(&mut InputProtocol::new(&map.to_vec()))
+        .unwrap();
+    let mut st = DynamicStruct::default();
+    st.insert(1, value);
+    let buffer = st.to_vec();
+    assert_eq!(DynamicStruct::from_slice(&buffer).unwrap().to_vec(), buffer);
+    let mut input = InputProtocol::new(&buffer);
+    input.skip_field(Ty::Struct).unwrap();
+    assert!(input.read_n_bytes::<1>().is_err());
+
+    assert_eq!(
+        BTreeMap::<String, Vec<i32>>::schema(),
+        TypeSchema::Map(
+            Box::new(TypeSchema::String),
+            Box::new(TypeSchema::List(Box::new(TypeSchema::I32)))
+        )
+    );
+}
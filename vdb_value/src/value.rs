@@ -13,6 +13,13 @@ pub enum DynamicValue {
         item_ty: Ty,
         items: Box<Vec<DynamicValue>>,
     },
+    Map {
+        key_ty: Ty,
+        value_ty: Ty,
+        entries: Box<Vec<(DynamicValue, DynamicValue)>>,
+    },
+    /// None is the null marker
+    Optional(Option<Box<DynamicValue>>),
     Stop,
 }
 
@@ -27,12 +34,18 @@ impl DynamicValue {
         match ty {
             Ty::I64 => Self::I64(0),
             Ty::F64 => Self::F64(0.),
-            Ty::Bytes => Self::Bytes(Box::new(vec![])),
+            Ty::Bytes => Self::Bytes(Box::default()),
             Ty::List => Self::List {
                 item_ty: Ty::Any,
                 items: Box::new(vec![]),
             },
-            Ty::Struct => Self::Struct(Box::new(DynamicStruct::default())),
+            Ty::Struct => Self::Struct(Box::default()),
+            Ty::Map => Self::Map {
+                key_ty: Ty::Any,
+                value_ty: Ty::Any,
+                entries: Box::new(vec![]),
+            },
+            Ty::Optional => Self::Optional(None),
             Ty::Stop => Self::Stop,
             Ty::Any => {
                 panic!("No default for Any ty allowed");
@@ -49,6 +62,8 @@ impl Value for DynamicValue {
             DynamicValue::Bytes(_) => Ty::Bytes,
             DynamicValue::Struct(_) => Ty::Struct,
             DynamicValue::List { .. } => Ty::List,
+            DynamicValue::Map { .. } => Ty::Map,
+            DynamicValue::Optional(_) => Ty::Optional,
             DynamicValue::Stop => Ty::Stop,
         }
     }
@@ -71,9 +86,38 @@ impl Value for DynamicValue {
                     v.from_input(input)?;
                     items_input.push(v);
                 }
-                *items = Box::new(items_input);
+                **items = items_input;
                 *item_ty = item_ty_input;
             }
+            DynamicValue::Map {
+                ref mut key_ty,
+                ref mut value_ty,
+                ref mut entries,
+            } => {
+                let (key_ty_input, value_ty_input, size) = input.read_map_header()?;
+
+                let mut entries_input = Vec::with_capacity(size as usize);
+                for _i in 0..size {
+                    let mut k = DynamicValue::default_for_ty(key_ty_input);
+                    k.from_input(input)?;
+                    let mut v = DynamicValue::default_for_ty(value_ty_input);
+                    v.from_input(input)?;
+                    entries_input.push((k, v));
+                }
+                **entries = entries_input;
+                *key_ty = key_ty_input;
+                *value_ty = value_ty_input;
+            }
+            DynamicValue::Optional(ref mut v) => {
+                *v = match input.read_ty()? {
+                    Ty::Stop => None,
+                    ty => {
+                        let mut value = DynamicValue::default_for_ty(ty);
+                        value.from_input(input)?;
+                        Some(Box::new(value))
+                    }
+                };
+            }
             DynamicValue::Stop => {
                 // do nothing
             }
@@ -93,6 +137,24 @@ impl Value for DynamicValue {
                     item.to_output(output);
                 }
             }
+            DynamicValue::Map {
+                key_ty,
+                value_ty,
+                entries,
+            } => {
+                output.write_map_header(*key_ty, *value_ty, entries.len() as u32);
+                for (k, v) in entries.iter() {
+                    k.to_output(output);
+                    v.to_output(output);
+                }
+            }
+            DynamicValue::Optional(v) => match v {
+                Some(v) => {
+                    output.write_ty(v.ty());
+                    v.to_output(output);
+                }
+                None => output.write_ty(Ty::Stop),
+            },
             DynamicValue::Stop => {
                 // do nothing
             }
@@ -101,23 +163,33 @@ impl Value for DynamicValue {
 }
 
 /// A dynamic value bag to hold dynamic values
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct DynamicStruct {
     fields: BTreeMap<u8, DynamicValue>,
 }
 
-impl Default for DynamicStruct {
-    fn default() -> Self {
-        Self {
-            fields: Default::default(),
-        }
-    }
-}
-
 impl DynamicStruct {
     pub fn insert(&mut self, index: u8, value: DynamicValue) -> Option<DynamicValue> {
         self.fields.insert(index, value)
     }
+
+    pub fn get(&self, index: u8) -> Option<&DynamicValue> {
+        self.fields.get(&index)
+    }
+
+    /// get value by field index path, e.g: [3, 1] means field 1 of the
+    /// struct at field 3
+    pub fn get_path(&self, path: &[u8]) -> Option<&DynamicValue> {
+        let (last, parents) = path.split_last()?;
+        let mut current = self;
+        for index in parents {
+            match current.get(*index)? {
+                DynamicValue::Struct(st) => current = st,
+                _ => return None,
+            }
+        }
+        current.get(*last)
+    }
 }
 
 impl Value for DynamicStruct {